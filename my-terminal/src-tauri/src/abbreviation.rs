@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::alias::{tokenize, Token};
+use crate::config::ConfigStore;
+use crate::error::CommandResponse;
+
+/// The input line after expanding an abbreviation, and where the cursor now
+/// is. Like the cursor passed in, it counts UTF-16 code units, as the input
+/// element does.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Expansion {
+    pub line: String,
+    pub cursor: usize,
+}
+
+/// Expands the word ending at byte `cursor` if it's an abbreviation typed as
+/// a command, the first word of the line or after `;`, `&&`, `|`.
+pub fn expand(
+    line: &str,
+    cursor: usize,
+    abbreviations: &BTreeMap<String, String>,
+) -> Option<String> {
+    let mut start = 0;
+    let mut command = true;
+    for token in tokenize(line) {
+        let end = start + text(&token).len();
+        match token {
+            Token::Word(word) => {
+                if command && end == cursor {
+                    let value = abbreviations.get(word)?;
+                    return Some(format!("{}{value}{}", &line[..start], &line[end..]));
+                }
+                command = false;
+            }
+            Token::Separator(_) => command = true,
+            Token::Space(_) => {}
+        }
+        if end >= cursor {
+            break;
+        }
+        start = end;
+    }
+    None
+}
+
+fn text<'a>(token: &Token<'a>) -> &'a str {
+    match token {
+        Token::Word(text) | Token::Space(text) | Token::Separator(text) => text,
+    }
+}
+
+/// The byte offset `units` UTF-16 code units into `line`, if that's on a
+/// character boundary.
+fn byte_offset(line: &str, units: usize) -> Option<usize> {
+    let mut counted = 0;
+    for (i, c) in line.char_indices() {
+        if counted >= units {
+            return (counted == units).then_some(i);
+        }
+        counted += c.len_utf16();
+    }
+    (counted == units).then_some(line.len())
+}
+
+/// Like `expand`, but with the cursor in UTF-16 code units, before and after.
+fn expand_input(
+    line: &str,
+    cursor: usize,
+    abbreviations: &BTreeMap<String, String>,
+) -> Option<Expansion> {
+    let at = byte_offset(line, cursor)?;
+    let expanded = expand(line, at, abbreviations)?;
+    let after = line.len() - at;
+    let cursor = expanded[..expanded.len() - after].encode_utf16().count();
+    Some(Expansion {
+        line: expanded,
+        cursor,
+    })
+}
+
+/// Expands the abbreviation just before the cursor in the input line, when
+/// space or enter is pressed. Nothing to expand gives `None`.
+#[tauri::command]
+pub fn expand_abbreviation(
+    config: State<'_, ConfigStore>,
+    line: String,
+    cursor: usize,
+) -> CommandResponse<Option<Expansion>> {
+    Ok(expand_input(&line, cursor, &config.config().abbreviations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> BTreeMap<String, String> {
+        [("gco", "git checkout"), ("gp", "git push")]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn expanded(line: &str, cursor: usize) -> Option<String> {
+        expand(line, cursor, &table())
+    }
+
+    #[test]
+    fn expands_commands() {
+        assert_eq!(expanded("gco", 3).as_deref(), Some("git checkout"));
+        assert_eq!(
+            expanded("gco main", 3).as_deref(),
+            Some("git checkout main")
+        );
+        assert_eq!(expanded("  gco", 5).as_deref(), Some("  git checkout"));
+        assert_eq!(expanded("make; gp", 8).as_deref(), Some("make; git push"));
+        assert_eq!(
+            expanded("make && gp", 10).as_deref(),
+            Some("make && git push")
+        );
+        assert_eq!(
+            expanded("ls | gco", 8).as_deref(),
+            Some("ls | git checkout")
+        );
+        assert_eq!(expanded("gp;gco", 6).as_deref(), Some("gp;git checkout"));
+    }
+
+    #[test]
+    fn leaves_other_words_alone() {
+        assert_eq!(expanded("echo gco", 8), None);
+        assert_eq!(expanded("git gp", 6), None);
+        assert_eq!(expanded("gcox", 4), None);
+        assert_eq!(expanded("", 0), None);
+    }
+
+    #[test]
+    fn only_expands_the_word_before_the_cursor() {
+        assert_eq!(expanded("gco", 2), None);
+        assert_eq!(expanded("gco", 0), None);
+        assert_eq!(expanded("gco ", 4), None);
+        assert_eq!(expanded("gco; gp", 3).as_deref(), Some("git checkout; gp"));
+    }
+
+    #[test]
+    fn leaves_quoted_words_alone() {
+        assert_eq!(expanded("'gco'", 5), None);
+        assert_eq!(expanded("\"gp\"", 4), None);
+        assert_eq!(expanded("echo 'a; gco'", 12), None);
+    }
+
+    #[test]
+    fn counts_the_cursor_in_utf16() {
+        let expand = |line: &str, cursor| expand_input(line, cursor, &table());
+        // "😀" is two UTF-16 code units and four bytes.
+        assert_eq!(
+            expand("echo 😀; gco", 12),
+            Some(Expansion {
+                line: "echo 😀; git checkout".into(),
+                cursor: 21,
+            })
+        );
+        assert_eq!(
+            expand("é; gp x", 5),
+            Some(Expansion {
+                line: "é; git push x".into(),
+                cursor: 11,
+            })
+        );
+        // Halfway through a surrogate pair, or past the end.
+        assert_eq!(expand("😀 gco", 1), None);
+        assert_eq!(expand("gco", 4), None);
+    }
+
+    #[test]
+    fn finds_byte_offsets() {
+        assert_eq!(byte_offset("a😀b", 0), Some(0));
+        assert_eq!(byte_offset("a😀b", 1), Some(1));
+        assert_eq!(byte_offset("a😀b", 2), None);
+        assert_eq!(byte_offset("a😀b", 3), Some(5));
+        assert_eq!(byte_offset("a😀b", 4), Some(6));
+        assert_eq!(byte_offset("a😀b", 5), None);
+    }
+}
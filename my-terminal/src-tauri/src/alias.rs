@@ -0,0 +1,547 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::config::ConfigStore;
+use crate::error::{CommandError, CommandResponse};
+use crate::session::SessionManager;
+
+/// Where an alias comes from; later ones win.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AliasSource {
+    /// Built in, or from `.quicktermrc`.
+    Config,
+    Profile,
+    Project,
+    /// Defined with the `alias` builtin; lasts as long as the session.
+    Session,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Alias {
+    pub name: String,
+    pub value: String,
+    pub source: AliasSource,
+}
+
+/// Whether `name` can be used as an alias: something typed as the first word
+/// of a command.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(|c: char| {
+            c.is_whitespace() || matches!(c, '=' | '\'' | '"' | '\\' | '$' | ';' | '&' | '|')
+        })
+}
+
+/// A piece of a command line: a word exactly as typed, quotes and all, or
+/// what separates commands. Redirections such as `2>&1`, `&>out` and `<&3`
+/// stay in one word.
+#[derive(Debug, PartialEq)]
+pub enum Token<'a> {
+    Word(&'a str),
+    Space(&'a str),
+    /// `;`, `&`, `&&`, `|`, `||` or a newline, after which a new command
+    /// starts.
+    Separator(&'a str),
+}
+
+/// Splits a line the way the shell would, without removing any quoting. An
+/// unterminated quote runs to the end of the line.
+pub fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c == '\n' || c == ';' || c == '|' || (c == '&' && !redirects(line, start, None)) {
+            chars.next();
+            let mut end = start + 1;
+            if let Some(&(_, next)) = chars.peek() {
+                if (c == '&' || c == '|') && next == c {
+                    chars.next();
+                    end += 1;
+                }
+            }
+            tokens.push(Token::Separator(&line[start..end]));
+        } else if c.is_whitespace() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c == '\n' || !c.is_whitespace() {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Space(&line[start..end]));
+        } else {
+            let mut end = start;
+            let mut quote = None;
+            let mut prev = None;
+            while let Some(&(i, c)) = chars.peek() {
+                match quote {
+                    None if c == '&' && redirects(line, i, prev) => {}
+                    None if c.is_whitespace() || matches!(c, ';' | '&' | '|') => break,
+                    None if c == '\'' || c == '"' => quote = Some(c),
+                    Some(q) if c == q => quote = None,
+                    _ => {}
+                }
+                chars.next();
+                end = i + c.len_utf8();
+                prev = Some(c);
+                // A backslash keeps the next character, except in single quotes.
+                if c == '\\' && quote != Some('\'') {
+                    if let Some((i, escaped)) = chars.next() {
+                        end = i + escaped.len_utf8();
+                    }
+                }
+            }
+            tokens.push(Token::Word(&line[start..end]));
+        }
+    }
+    tokens
+}
+
+/// Whether the `&` at byte `at`, after `prev` in the same word, is part of a
+/// redirection (`>&`, `<&`, `&>`) rather than a separator.
+fn redirects(line: &str, at: usize, prev: Option<char>) -> bool {
+    matches!(prev, Some('>' | '<')) || line[at + 1..].starts_with('>')
+}
+
+/// Puts the arguments into an alias's `$1`…`$9` and `$@`, which are left
+/// alone in single quotes or after a backslash. Arguments go in as typed, or
+/// unquoted and escaped inside double quotes. Arguments that fill no
+/// placeholder, redirections included, go after it. Returns `None` if the
+/// value has no placeholders, in which case all of them go after it.
+fn substitute(value: &str, args: &[&str]) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut used = false;
+    let mut filled = vec![false; args.len()];
+    let mut quote = None;
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        let inserted: Option<&[&str]> = match c {
+            '\'' | '"' if quote.is_none() => {
+                quote = Some(c);
+                None
+            }
+            c if quote == Some(c) => {
+                quote = None;
+                None
+            }
+            '\\' if quote != Some('\'') => {
+                out.push(c);
+                out.extend(chars.next());
+                continue;
+            }
+            '$' if quote != Some('\'') => match chars.peek() {
+                Some('@') => {
+                    filled.fill(true);
+                    Some(args)
+                }
+                Some(&digit @ '1'..='9') => {
+                    let n = digit as usize - '1' as usize;
+                    if let Some(filled) = filled.get_mut(n) {
+                        *filled = true;
+                    }
+                    Some(args.get(n..=n).unwrap_or_default())
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some(inserted) = inserted else {
+            out.push(c);
+            continue;
+        };
+        chars.next();
+        used = true;
+        if quote == Some('"') {
+            let unquoted: Vec<_> = inserted.iter().map(|arg| unquote(arg)).collect();
+            for c in unquoted.join(" ").chars() {
+                if matches!(c, '"' | '\\' | '$' | '`') {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+        } else {
+            out.push_str(&inserted.join(" "));
+        }
+    }
+    for (arg, _) in args.iter().zip(filled).filter(|(_, filled)| !filled) {
+        out.push(' ');
+        out.push_str(arg);
+    }
+    used.then_some(out)
+}
+
+/// Expands the aliases at the start of each command in `line`: `ll -h`,
+/// `gs && gp`, `ll | less`. Aliases may use other aliases. One that uses
+/// itself (`ls` for `ls --color=auto`) means the real command, but a longer
+/// loop is an error.
+pub fn expand(line: &str, aliases: &BTreeMap<String, String>) -> CommandResponse<String> {
+    expand_within(line, aliases, &mut Vec::new())
+}
+
+fn expand_within(
+    line: &str,
+    aliases: &BTreeMap<String, String>,
+    chain: &mut Vec<String>,
+) -> CommandResponse<String> {
+    let tokens = tokenize(line);
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let name = match tokens[i] {
+            Token::Word(name) => name,
+            Token::Space(text) | Token::Separator(text) => {
+                out.push_str(text);
+                i += 1;
+                continue;
+            }
+        };
+        // The rest of this command, up to the next separator.
+        let end = tokens[i..]
+            .iter()
+            .position(|token| matches!(token, Token::Separator(_)))
+            .map_or(tokens.len(), |n| i + n);
+        let rest = &tokens[i + 1..end];
+
+        let value = aliases
+            .get(name)
+            .filter(|_| chain.last().map(String::as_str) != Some(name));
+        match value {
+            Some(_) if chain.iter().any(|expanding| expanding == name) => {
+                chain.push(name.to_string());
+                return Err(CommandError::AliasCycle {
+                    chain: std::mem::take(chain),
+                });
+            }
+            Some(value) => {
+                let args: Vec<&str> = rest
+                    .iter()
+                    .filter_map(|token| match token {
+                        Token::Word(word) => Some(*word),
+                        _ => None,
+                    })
+                    .collect();
+                chain.push(name.to_string());
+                match substitute(value, &args) {
+                    Some(text) => out.push_str(&expand_within(&text, aliases, chain)?),
+                    None => {
+                        out.push_str(&expand_within(value, aliases, chain)?);
+                        push_tokens(&mut out, rest);
+                    }
+                }
+                chain.pop();
+            }
+            None => {
+                out.push_str(name);
+                push_tokens(&mut out, rest);
+            }
+        }
+        i = end;
+    }
+    Ok(out)
+}
+
+fn push_tokens(out: &mut String, tokens: &[Token<'_>]) {
+    for token in tokens {
+        match token {
+            Token::Word(text) | Token::Space(text) | Token::Separator(text) => out.push_str(text),
+        }
+    }
+}
+
+/// Removes the quoting from a word, as the shell would: `'a b'`, `"a \"b\""`
+/// and `a\ b` all give the text inside.
+fn unquote(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '\\') => out.extend(chars.next()),
+            (Some('"'), '\\') => match chars.next() {
+                Some(escaped @ ('"' | '\\' | '$' | '`')) => out.push(escaped),
+                Some(other) => {
+                    out.push('\\');
+                    out.push(other);
+                }
+                None => out.push('\\'),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Splits an `alias` builtin's `name=value`, unquoting the value:
+/// `gco='git checkout'`.
+pub fn parse_definition(definition: &str) -> CommandResponse<(String, String)> {
+    let definition = definition.trim();
+    let invalid = || CommandError::InvalidAlias {
+        name: definition.into(),
+    };
+    let (name, value) = definition.split_once('=').ok_or_else(invalid)?;
+    if !valid_name(name) {
+        return Err(invalid());
+    }
+    Ok((name.to_string(), unquote(value)))
+}
+
+/// Expands the aliases in a command line typed in the session.
+#[tauri::command]
+pub fn expand_aliases(
+    manager: State<'_, SessionManager>,
+    config: State<'_, ConfigStore>,
+    id: u32,
+    line: String,
+) -> CommandResponse<String> {
+    let aliases = manager.get(id)?.aliases(&config.config());
+    let table = aliases
+        .into_iter()
+        .map(|alias| (alias.name, alias.value))
+        .collect();
+    expand(&line, &table)
+}
+
+/// Every alias in effect in the session, by name.
+#[tauri::command]
+pub fn list_aliases(
+    manager: State<'_, SessionManager>,
+    config: State<'_, ConfigStore>,
+    id: u32,
+) -> CommandResponse<Vec<Alias>> {
+    Ok(manager.get(id)?.aliases(&config.config()))
+}
+
+/// The `alias name=value` builtin.
+#[tauri::command]
+pub fn define_alias(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    definition: String,
+) -> CommandResponse<()> {
+    let (name, value) = parse_definition(&definition)?;
+    manager.get(id)?.set_alias(name, Some(value));
+    Ok(())
+}
+
+/// The `unalias name` builtin. Aliases from config, profile or project are
+/// hidden for the rest of the session.
+#[tauri::command]
+pub fn remove_alias(
+    manager: State<'_, SessionManager>,
+    config: State<'_, ConfigStore>,
+    id: u32,
+    name: String,
+) -> CommandResponse<()> {
+    let session = manager.get(id)?;
+    if !session
+        .aliases(&config.config())
+        .iter()
+        .any(|alias| alias.name == name)
+    {
+        return Err(CommandError::UnknownAlias { name });
+    }
+    session.set_alias(name, None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(aliases: &[(&str, &str)]) -> BTreeMap<String, String> {
+        aliases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn expanded(line: &str, aliases: &[(&str, &str)]) -> String {
+        expand(line, &table(aliases)).unwrap()
+    }
+
+    #[test]
+    fn appends_arguments_without_placeholders() {
+        assert_eq!(
+            expanded("ll -h /tmp", &[("ll", "ls -la")]),
+            "ls -la -h /tmp"
+        );
+        assert_eq!(expanded("ll", &[("ll", "ls -la")]), "ls -la");
+        assert_eq!(expanded("  ll  x", &[("ll", "ls -la")]), "  ls -la  x");
+    }
+
+    #[test]
+    fn leaves_other_words_alone() {
+        assert_eq!(expanded("echo ll", &[("ll", "ls -la")]), "echo ll");
+        assert_eq!(expanded("lll", &[("ll", "ls -la")]), "lll");
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        let aliases = [
+            ("gco", "git checkout $1"),
+            ("e", "echo [$@]"),
+            ("sw", "echo $2 $1"),
+        ];
+        assert_eq!(expanded("gco main", &aliases), "git checkout main");
+        assert_eq!(expanded("e a b  c", &aliases), "echo [a b c]");
+        assert_eq!(expanded("sw a b", &aliases), "echo b a");
+        assert_eq!(expanded("gco", &aliases), "git checkout ");
+        assert_eq!(expanded("gco a b", &aliases), "git checkout a b");
+        assert_eq!(expanded("sw a b c", &aliases), "echo b a c");
+    }
+
+    #[test]
+    fn keeps_redirections_after_placeholders() {
+        let aliases = [("gcm", "git commit -m $1"), ("e", "echo [$@]")];
+        assert_eq!(expanded("gcm x 2>&1", &aliases), "git commit -m x 2>&1");
+        assert_eq!(
+            expanded("gcm x > out.txt", &aliases),
+            "git commit -m x > out.txt"
+        );
+        assert_eq!(
+            expanded("gcm x &>log <&3", &aliases),
+            "git commit -m x &>log <&3"
+        );
+        assert_eq!(expanded("e a >&2", &aliases), "echo [a >&2]");
+    }
+
+    #[test]
+    fn redirections_are_not_separators() {
+        assert_eq!(
+            tokenize("a 2>&1&b"),
+            [
+                Token::Word("a"),
+                Token::Space(" "),
+                Token::Word("2>&1"),
+                Token::Separator("&"),
+                Token::Word("b"),
+            ]
+        );
+        assert_eq!(
+            tokenize("&>out x<&3 &&y"),
+            [
+                Token::Word("&>out"),
+                Token::Space(" "),
+                Token::Word("x<&3"),
+                Token::Space(" "),
+                Token::Separator("&&"),
+                Token::Word("y"),
+            ]
+        );
+        let aliases = [("ll", "ls -la")];
+        assert_eq!(expanded("ll >&2 && ll", &aliases), "ls -la >&2 && ls -la");
+    }
+
+    #[test]
+    fn keeps_quoted_arguments_whole() {
+        let aliases = [("gcm", "git commit -m $1"), ("ll", "ls -la")];
+        assert_eq!(
+            expanded(r#"gcm "fix: a b""#, &aliases),
+            r#"git commit -m "fix: a b""#
+        );
+        assert_eq!(expanded("gcm 'it''s'", &aliases), "git commit -m 'it''s'");
+        assert_eq!(expanded(r"gcm a\ b", &aliases), r"git commit -m a\ b");
+        assert_eq!(expanded(r#"ll "my dir""#, &aliases), r#"ls -la "my dir""#);
+    }
+
+    #[test]
+    fn placeholders_in_single_quotes_or_escaped_are_literal() {
+        let aliases = [
+            ("col", "awk '{print $1}'"),
+            ("dollar", r"echo \$1 $1"),
+            ("dq", r#"echo "$1""#),
+        ];
+        assert_eq!(expanded("col x", &aliases), "awk '{print $1}' x");
+        assert_eq!(expanded("dollar x", &aliases), r"echo \$1 x");
+        assert_eq!(expanded("dq 'a b'", &aliases), r#"echo "a b""#);
+        assert_eq!(
+            expanded(r#"dq '"$HOME"'"#, &aliases),
+            r#"echo "\"\$HOME\"""#
+        );
+    }
+
+    #[test]
+    fn expands_every_command_on_the_line() {
+        let aliases = [("gs", "git status"), ("ll", "ls -la")];
+        assert_eq!(expanded("gs && ll", &aliases), "git status && ls -la");
+        assert_eq!(
+            expanded("ll|gs;ll &", &aliases),
+            "ls -la|git status;ls -la &"
+        );
+        assert_eq!(expanded("echo x || gs", &aliases), "echo x || git status");
+    }
+
+    #[test]
+    fn separators_in_quotes_are_not_commands() {
+        let aliases = [("ll", "ls -la")];
+        assert_eq!(expanded("echo 'a; ll'", &aliases), "echo 'a; ll'");
+        assert_eq!(expanded(r#"echo "a && ll""#, &aliases), r#"echo "a && ll""#);
+        assert_eq!(expanded(r"echo a\;ll", &aliases), r"echo a\;ll");
+    }
+
+    #[test]
+    fn unterminated_quotes_run_to_the_end() {
+        let aliases = [("gcm", "git commit -m $1")];
+        assert_eq!(
+            expanded("gcm 'oops && ll", &aliases),
+            "git commit -m 'oops && ll"
+        );
+    }
+
+    #[test]
+    fn chains_aliases() {
+        let aliases = [
+            ("l2", "ll -h"),
+            ("ll", "ls -la"),
+            ("up", "gp && gs"),
+            ("gp", "git push"),
+            ("gs", "git status"),
+        ];
+        assert_eq!(expanded("l2 /tmp", &aliases), "ls -la -h /tmp");
+        assert_eq!(expanded("up", &aliases), "git push && git status");
+    }
+
+    #[test]
+    fn an_alias_using_itself_means_the_command() {
+        let aliases = [("ls", "ls --color=auto"), ("ll", "ls -la")];
+        assert_eq!(expanded("ls x", &aliases), "ls --color=auto x");
+        assert_eq!(expanded("ll", &aliases), "ls --color=auto -la");
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let aliases = table(&[("a", "b x"), ("b", "c"), ("c", "a")]);
+        match expand("a", &aliases) {
+            Err(CommandError::AliasCycle { chain }) => assert_eq!(chain, ["a", "b", "c", "a"]),
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_definitions() {
+        let parse = |definition| parse_definition(definition).unwrap();
+        assert_eq!(
+            parse("gco='git checkout'"),
+            ("gco".into(), "git checkout".into())
+        );
+        assert_eq!(
+            parse(r#"e="echo \"$1\"""#),
+            ("e".into(), r#"echo "$1""#.into())
+        );
+        assert_eq!(parse(r"x=a\ b"), ("x".into(), "a b".into()));
+        assert_eq!(
+            parse("col='awk '\\''{print $1}'\\'"),
+            ("col".into(), "awk '{print $1}'".into())
+        );
+        assert_eq!(parse("empty="), ("empty".into(), String::new()));
+        assert!(parse_definition("no value").is_err());
+        assert!(parse_definition("=x").is_err());
+        assert!(parse_definition("a b=x").is_err());
+    }
+}
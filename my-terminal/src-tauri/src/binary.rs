@@ -0,0 +1,393 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{async_runtime, State};
+use tempfile::NamedTempFile;
+
+use crate::error::{CommandError, CommandResponse};
+
+/// Output is examined for binary data until this many bytes have been seen,
+/// so no more than this is ever shown of a binary stream.
+const SNIFF_LEN: usize = 8 * 1024;
+/// Magic numbers sit at the very start of a file.
+const MAGIC_LEN: usize = 512;
+/// Output with more than one control character in this many bytes that
+/// text never contains is taken to be binary.
+const SUSPICIOUS_RATIO: usize = 32;
+/// Raw bytes kept per output for saving; past this only the size is counted.
+const MAX_KEPT_BYTES: u64 = 256 << 20;
+/// Outputs kept for saving, and their bytes in all; older ones are deleted
+/// first. The temporary directory is often in memory.
+const MAX_KEPT: usize = 16;
+const MAX_KEPT_TOTAL: u64 = 512 << 20;
+
+/// Control characters that text, including terminal escape sequences, never
+/// contains.
+fn is_suspicious(byte: u8) -> bool {
+    match byte {
+        // BEL, BS, TAB, LF, VT, FF, CR, SO, SI and ESC all turn up in program
+        // output.
+        0x07..=0x0f | 0x1b => false,
+        0x00..=0x1f | 0x7f => true,
+        _ => false,
+    }
+}
+
+fn is_utf8(data: &[u8]) -> bool {
+    match std::str::from_utf8(data) {
+        Ok(_) => true,
+        // A character cut off at the end of the sample is fine.
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// The file type named by `head`'s magic number, unless it's a text format
+/// such as HTML or a script.
+fn magic(head: &[u8]) -> Option<infer::Type> {
+    infer::get(head).filter(|kind| kind.matcher_type() != infer::MatcherType::Text)
+}
+
+/// Watches a stream of output for binary data. Output passes through until
+/// some is found; from then on everything, including what was already seen,
+/// is diverted into a [`BinaryCapture`] instead.
+#[derive(Default)]
+pub struct BinaryFilter {
+    /// The first `SNIFF_LEN` bytes.
+    head: Vec<u8>,
+    suspicious: usize,
+    nul: bool,
+    capture: Option<BinaryCapture>,
+}
+
+impl BinaryFilter {
+    /// Returns whether `data` should be shown.
+    pub fn filter(&mut self, data: &[u8]) -> bool {
+        if let Some(capture) = &mut self.capture {
+            capture.write(data);
+            return false;
+        }
+        let seen = self.head.len();
+        if seen >= SNIFF_LEN {
+            return true;
+        }
+        let sniffed = &data[..data.len().min(SNIFF_LEN - seen)];
+        self.head.extend_from_slice(sniffed);
+        self.nul |= sniffed.contains(&0);
+        self.suspicious += sniffed.iter().filter(|&&byte| is_suspicious(byte)).count();
+        if !self.looks_binary(seen) {
+            return true;
+        }
+
+        let mut capture = BinaryCapture::new(&self.head);
+        capture.write(&self.head[..seen]);
+        capture.write(data);
+        self.capture = Some(capture);
+        false
+    }
+
+    fn looks_binary(&self, seen: usize) -> bool {
+        if self.nul || self.suspicious * SUSPICIOUS_RATIO > self.head.len() {
+            return true;
+        }
+        // Some magic numbers are ordinary text ("MZ", "ID3"), so they only
+        // count for output that isn't UTF-8 either.
+        seen < MAGIC_LEN && magic(&self.head).is_some() && !is_utf8(&self.head)
+    }
+
+    /// The binary output, if the stream turned out to be binary.
+    pub fn finish(self) -> Option<BinaryCapture> {
+        self.capture
+    }
+}
+
+/// Binary output held in a temporary file, deleted on drop.
+pub struct BinaryCapture {
+    /// `None` if the file couldn't be created.
+    file: Option<NamedTempFile>,
+    /// Writing failed, so nothing more is kept.
+    failed: bool,
+    size: u64,
+    kept: u64,
+    kind: Option<infer::Type>,
+}
+
+impl BinaryCapture {
+    fn new(head: &[u8]) -> Self {
+        let file = tempfile::Builder::new()
+            .prefix("quickterm-")
+            .suffix(".bin")
+            .tempfile()
+            .ok();
+        BinaryCapture {
+            file,
+            failed: false,
+            size: 0,
+            kept: 0,
+            kind: magic(head),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+        let Some(file) = self.file.as_mut().filter(|_| !self.failed) else {
+            return;
+        };
+        let room = MAX_KEPT_BYTES.saturating_sub(self.kept);
+        let data = &data[..data.len().min(room as usize)];
+        match file.write_all(data) {
+            Ok(()) => self.kept += data.len() as u64,
+            Err(_) => self.failed = true,
+        }
+    }
+
+    fn summary(&self, id: u64) -> BinarySummary {
+        BinarySummary {
+            id,
+            size: self.size,
+            mime_type: self
+                .kind
+                .map_or("application/octet-stream", |kind| kind.mime_type())
+                .into(),
+            extension: self.kind.map(|kind| kind.extension().into()),
+            truncated: self.kept < self.size,
+        }
+    }
+
+    /// The kept bytes, readable even once the capture is dropped.
+    fn open(&self) -> io::Result<File> {
+        match &self.file {
+            Some(file) if !(self.failed && self.kept == 0) => file.reopen(),
+            _ => Err(io::Error::other("the output could not be kept")),
+        }
+    }
+}
+
+/// What is shown in place of binary output.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinarySummary {
+    /// Pass to `save_binary_output`.
+    id: u64,
+    size: u64,
+    mime_type: String,
+    extension: Option<String>,
+    /// Only the start of the output can be saved.
+    truncated: bool,
+}
+
+/// The most recent binary outputs, kept so they can still be saved.
+#[derive(Default)]
+pub struct BinaryOutputs {
+    next_id: AtomicU64,
+    kept: Mutex<VecDeque<(u64, BinaryCapture)>>,
+}
+
+impl BinaryOutputs {
+    pub fn keep(&self, capture: BinaryCapture) -> BinarySummary {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let summary = capture.summary(id);
+        let mut kept = self.kept.lock().unwrap();
+        kept.push_back((id, capture));
+        let mut total: u64 = kept.iter().map(|(_, capture)| capture.kept).sum();
+        while kept.len() > MAX_KEPT || (total > MAX_KEPT_TOTAL && kept.len() > 1) {
+            if let Some((_, oldest)) = kept.pop_front() {
+                total -= oldest.kept;
+            }
+        }
+        summary
+    }
+}
+
+/// Writes the raw bytes of a binary output to `path`. Returns the number of
+/// bytes written.
+#[tauri::command]
+pub async fn save_binary_output(
+    outputs: State<'_, BinaryOutputs>,
+    id: u64,
+    path: String,
+) -> CommandResponse<u64> {
+    let mut source = {
+        let kept = outputs.kept.lock().unwrap();
+        let (_, capture) = kept
+            .iter()
+            .find(|(kept_id, _)| *kept_id == id)
+            .ok_or(CommandError::UnknownId { id })?;
+        capture.open().map_err(CommandError::io)?
+    };
+    // Up to `MAX_KEPT_BYTES`, so off the async workers.
+    let target = path.clone();
+    let copied =
+        async_runtime::spawn_blocking(move || io::copy(&mut source, &mut File::create(target)?))
+            .await
+            .map_err(CommandError::io)?;
+    copied.map_err(|err| match err.kind() {
+        io::ErrorKind::PermissionDenied => CommandError::PermissionDenied { path },
+        _ => CommandError::io(err),
+    })
+}
+
+#[tauri::command]
+pub fn discard_binary_output(outputs: State<'_, BinaryOutputs>, id: u64) -> CommandResponse<()> {
+    outputs
+        .kept
+        .lock()
+        .unwrap()
+        .retain(|(kept_id, _)| *kept_id != id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    /// Feeds `reads` through a filter, returning what was shown and the
+    /// capture, if any.
+    fn run(reads: &[&[u8]]) -> (Vec<u8>, Option<BinaryCapture>) {
+        let mut filter = BinaryFilter::default();
+        let mut shown = Vec::new();
+        for data in reads {
+            if filter.filter(data) {
+                shown.extend_from_slice(data);
+            }
+        }
+        (shown, filter.finish())
+    }
+
+    fn contents(capture: &BinaryCapture) -> Vec<u8> {
+        let mut data = Vec::new();
+        capture.open().unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn passes_text_through() {
+        let (shown, capture) = run(&[
+            b"hello\r\n\x1b[1mbold\x1b[0m\t\x07",
+            "héllo 世界".as_bytes(),
+        ]);
+        assert!(capture.is_none());
+        assert_eq!(
+            shown,
+            "hello\r\n\x1b[1mbold\x1b[0m\t\x07héllo 世界".as_bytes()
+        );
+        // Text that happens to start like an executable.
+        assert!(run(&[b"MZ is a text line\n"]).1.is_none());
+    }
+
+    #[test]
+    fn captures_binary_from_the_start() {
+        let (shown, capture) = run(&[b"some text first\n", b"then a \0 byte", b"and more"]);
+        assert_eq!(shown, b"some text first\n");
+        let capture = capture.unwrap();
+        assert_eq!(
+            contents(&capture),
+            b"some text first\nthen a \0 byteand more" as &[u8]
+        );
+        let summary = capture.summary(1);
+        assert_eq!(summary.size, 37);
+        assert!(!summary.truncated);
+        assert_eq!(summary.mime_type, "application/octet-stream");
+    }
+
+    #[test]
+    fn spots_control_characters_and_magic_numbers() {
+        let mut noisy = b"x".repeat(64);
+        noisy[10] = 0x01;
+        noisy[20] = 0x02;
+        noisy[30] = 0x03;
+        assert!(run(&[&noisy]).1.is_some());
+        noisy[30] = b'x';
+        assert!(run(&[&noisy]).1.is_none());
+
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        let capture = run(&[&png[..4], &png[4..]]).1.unwrap();
+        assert_eq!(capture.summary(1).mime_type, "image/png");
+        assert_eq!(capture.summary(1).extension.as_deref(), Some("png"));
+    }
+
+    #[test]
+    fn only_sniffs_the_start() {
+        let text = b"a".repeat(SNIFF_LEN - 1);
+        let (shown, capture) = run(&[&text, b"\0b"]);
+        assert_eq!(shown, text);
+        assert_eq!(capture.unwrap().size, SNIFF_LEN as u64 + 1);
+
+        // Past the window, even within the same read.
+        let (shown, capture) = run(&[&text, b"b\0", b"\0\0"]);
+        assert!(capture.is_none());
+        assert_eq!(shown.len(), SNIFF_LEN + 3);
+    }
+
+    #[test]
+    fn stops_keeping_at_the_cap() {
+        let (_, capture) = run(&[b"\0\0"]);
+        let mut capture = capture.unwrap();
+        // As if nearly all of the cap had been written already.
+        capture.kept = MAX_KEPT_BYTES - 3;
+        capture.size = MAX_KEPT_BYTES - 3;
+        assert!(!capture.summary(1).truncated);
+        capture.write(b"abcdef");
+        capture.write(b"ghi");
+        assert_eq!(capture.kept, MAX_KEPT_BYTES);
+        assert_eq!(capture.size, MAX_KEPT_BYTES + 6);
+        assert!(capture.summary(1).truncated);
+        assert_eq!(contents(&capture), b"\0\0abc" as &[u8]);
+    }
+
+    #[test]
+    fn saved_bytes_outlive_the_capture() {
+        let capture = run(&[b"\0data"]).1.unwrap();
+        let mut source = capture.open().unwrap();
+        drop(capture);
+        let mut data = Vec::new();
+        source.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"\0data");
+    }
+
+    fn capture(kept: u64) -> BinaryCapture {
+        BinaryCapture {
+            file: None,
+            failed: false,
+            size: kept,
+            kept,
+            kind: None,
+        }
+    }
+
+    fn kept_ids(outputs: &BinaryOutputs) -> Vec<u64> {
+        let kept = outputs.kept.lock().unwrap();
+        kept.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn keeps_a_bounded_number_of_outputs() {
+        let outputs = BinaryOutputs::default();
+        for _ in 0..MAX_KEPT + 2 {
+            outputs.keep(capture(1));
+        }
+        let ids: Vec<u64> = (3..=MAX_KEPT as u64 + 2).collect();
+        assert_eq!(kept_ids(&outputs), ids);
+    }
+
+    #[test]
+    fn evicts_the_oldest_outputs_past_the_total() {
+        let outputs = BinaryOutputs::default();
+        let quarter = MAX_KEPT_TOTAL / 4;
+        for kept in [quarter, quarter, quarter, 10] {
+            outputs.keep(capture(kept));
+        }
+        assert_eq!(kept_ids(&outputs), [1, 2, 3, 4]);
+        outputs.keep(capture(quarter + 1));
+        assert_eq!(kept_ids(&outputs), [2, 3, 4, 5]);
+        outputs.keep(capture(MAX_KEPT_TOTAL));
+        assert_eq!(kept_ids(&outputs), [6]);
+        outputs.keep(capture(1));
+        assert_eq!(kept_ids(&outputs), [7]);
+    }
+}
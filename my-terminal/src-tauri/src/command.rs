@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::{self, JoinHandle};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager, State};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::binary::{BinaryCapture, BinaryFilter, BinaryOutputs, BinarySummary};
+use crate::encoding::{OutputDecoder, OutputEncoding};
+use crate::error::{validate_cwd, validate_env, CommandError, CommandResponse};
+use crate::process::{isolate_process_group, signal_group, ProcessRegistry, Stage};
+use crate::shell::{command_flag, ShellConfig};
+
+/// Timeouts used when a caller doesn't pass `timeoutMs`. Background queries
+/// (prompt helpers like the git branch lookup) always get a limit so a slow
+/// network filesystem can't freeze the terminal.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandDefaults {
+    pub timeout_ms: Option<u64>,
+    pub background_timeout_ms: u64,
+}
+
+impl Default for CommandDefaults {
+    fn default() -> Self {
+        CommandDefaults {
+            timeout_ms: None,
+            background_timeout_ms: 2000,
+        }
+    }
+}
+
+impl CommandDefaults {
+    pub fn timeout(&self, requested: Option<u64>, background: bool) -> Option<Duration> {
+        let default = if background {
+            Some(self.background_timeout_ms)
+        } else {
+            self.timeout_ms
+        };
+        requested.or(default).map(Duration::from_millis)
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "event",
+    content = "data"
+)]
+pub enum CommandEvent {
+    Stdout {
+        chunk: String,
+    },
+    Stderr {
+        chunk: String,
+    },
+    StdoutBinary(BinarySummary),
+    StderrBinary(BinarySummary),
+    Finished {
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        duration_ms: u64,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandResult {
+    pub stdout: String,
+    pub stderr: String,
+    /// Set, and the stream left out of `stdout`, when it was binary.
+    pub stdout_binary: Option<BinarySummary>,
+    pub stderr_binary: Option<BinarySummary>,
+    pub exit_code: Option<i32>,
+    /// Set instead of `exit_code` when the process was killed by a signal.
+    pub signal: Option<i32>,
+    pub duration_ms: u64,
+    /// Milliseconds since the Unix epoch.
+    pub started_at: u64,
+}
+
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Builds `$SHELL -c command` (or the chosen shell's equivalent) with the
+/// working directory and environment applied by the OS rather than spliced
+/// into the shell source. Returns the shell's path alongside.
+fn shell_command(
+    command: &str,
+    shell: Option<&ShellConfig>,
+    cwd: Option<&str>,
+    env: Option<&HashMap<String, String>>,
+) -> CommandResponse<(Command, String)> {
+    let shell = shell.cloned().unwrap_or_default().resolve()?;
+    let program = shell.program.expect("resolved shell has a program");
+    let mut cmd = Command::new(&program);
+    cmd.args(&shell.args)
+        .arg(command_flag(&program))
+        .arg(command);
+    if let Some(cwd) = cwd {
+        validate_cwd(cwd)?;
+        cmd.current_dir(cwd);
+    }
+    if let Some(env) = env {
+        validate_env(env)?;
+        cmd.envs(env);
+    }
+    Ok((cmd, program))
+}
+
+/// Decodes as much of `pending` as forms complete UTF-8, leaving a trailing
+/// partial character in place for the next read to finish.
+pub fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    let rest = pending.split_off(valid);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    text
+}
+
+/// Sends a pipe's output as it arrives. If it turns out to be binary the
+/// chunks stop and a summary is sent when the pipe closes.
+fn forward(
+    app: AppHandle,
+    mut pipe: impl AsyncRead + Unpin + Send + 'static,
+    channel: Channel<CommandEvent>,
+    encoding: OutputEncoding,
+    wrap: fn(String) -> CommandEvent,
+    wrap_binary: fn(BinarySummary) -> CommandEvent,
+) -> JoinHandle<()> {
+    async_runtime::spawn(async move {
+        let mut buf = vec![0u8; 8192];
+        let mut decoder = OutputDecoder::new(encoding);
+        let mut binary = BinaryFilter::default();
+        loop {
+            match pipe.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if !binary.filter(&buf[..n]) {
+                        continue;
+                    }
+                    let chunk = decoder.decode(&buf[..n]);
+                    if !chunk.is_empty() {
+                        let _ = channel.send(wrap(chunk));
+                    }
+                }
+            }
+        }
+        let rest = decoder.finish();
+        if !rest.is_empty() {
+            let _ = channel.send(wrap(rest));
+        }
+        if let Some(capture) = binary.finish() {
+            let summary = app.state::<BinaryOutputs>().keep(capture);
+            let _ = channel.send(wrap_binary(summary));
+        }
+    })
+}
+
+/// Gives the pipe readers up to `grace` to reach the end once the command
+/// has exited. Something it left in the background can hold a pipe open
+/// indefinitely, so whatever arrives after that is dropped.
+async fn drain(mut pipes: [JoinHandle<()>; 2], grace: Duration) {
+    let ended = async {
+        for pipe in &mut pipes {
+            let _ = pipe.await;
+        }
+    };
+    if tokio::time::timeout(grace, ended).await.is_err() {
+        for pipe in &pipes {
+            pipe.abort();
+        }
+    }
+}
+
+/// Reads a pipe to the end. Binary output is captured rather than buffered.
+fn collect(
+    mut pipe: impl AsyncRead + Unpin + Send + 'static,
+) -> JoinHandle<(Vec<u8>, Option<BinaryCapture>)> {
+    async_runtime::spawn(async move {
+        let mut buf = vec![0u8; 8192];
+        let mut text = Vec::new();
+        let mut binary = BinaryFilter::default();
+        loop {
+            match pipe.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if binary.filter(&buf[..n]) {
+                        text.extend_from_slice(&buf[..n]);
+                    }
+                }
+            }
+        }
+        (text, binary.finish())
+    })
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_command(
+    defaults: State<'_, Mutex<CommandDefaults>>,
+    binaries: State<'_, BinaryOutputs>,
+    registry: State<'_, ProcessRegistry>,
+    id: Option<u64>,
+    command: String,
+    cwd: Option<String>,
+    env: Option<HashMap<String, String>>,
+    shell: Option<ShellConfig>,
+    encoding: Option<String>,
+    timeout_ms: Option<u64>,
+    background: Option<bool>,
+) -> CommandResponse<CommandResult> {
+    let encoding = OutputEncoding::parse(encoding.as_deref())?;
+    let timeout = defaults
+        .lock()
+        .unwrap()
+        .timeout(timeout_ms, background.unwrap_or(false));
+    let (mut cmd, program) = shell_command(&command, shell.as_ref(), cwd.as_deref(), env.as_ref())?;
+    isolate_process_group(&mut cmd);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut cmd = tokio::process::Command::from(cmd);
+    cmd.kill_on_drop(true);
+
+    let started_at = unix_millis(SystemTime::now());
+    let started = Instant::now();
+    let mut child = cmd.spawn().map_err(|e| CommandError::spawn(&program, e))?;
+    let pid = child.id().expect("freshly spawned child has a pid");
+    let id = id.unwrap_or_else(|| registry.reserve());
+    registry.register_as(id, pid, None, encoding.default_input());
+    let stdout = collect(child.stdout.take().expect("stdout is piped"));
+    let stderr = collect(child.stderr.take().expect("stderr is piped"));
+    let output = async {
+        let status = child.wait().await.map_err(CommandError::io)?;
+        let stdout = stdout.await.map_err(CommandError::io)?;
+        let stderr = stderr.await.map_err(CommandError::io)?;
+        Ok::<_, CommandError>((status, stdout, stderr))
+    };
+    let output = match timeout {
+        Some(limit) => match tokio::time::timeout(limit, output).await {
+            Ok(output) => output,
+            Err(_) => {
+                signal_group(pid, Stage::Kill);
+                Err(CommandError::Timeout {
+                    after_ms: limit.as_millis() as u64,
+                })
+            }
+        },
+        None => output.await,
+    };
+    registry.unregister(id);
+    let (status, (stdout, stdout_binary), (stderr, stderr_binary)) = output?;
+
+    let decode = |bytes: &[u8]| {
+        let mut decoder = OutputDecoder::new(encoding);
+        decoder.decode(bytes) + &decoder.finish()
+    };
+    let result = CommandResult {
+        stdout: decode(&stdout),
+        stderr: decode(&stderr),
+        stdout_binary: stdout_binary.map(|capture| binaries.keep(capture)),
+        stderr_binary: stderr_binary.map(|capture| binaries.keep(capture)),
+        exit_code: status.code(),
+        signal: exit_signal(&status),
+        duration_ms: started.elapsed().as_millis() as u64,
+        started_at,
+    };
+    if status.success() {
+        Ok(result)
+    } else {
+        Err(CommandError::NonZeroExit(Box::new(result)))
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_command_stream(
+    app: AppHandle,
+    registry: State<'_, ProcessRegistry>,
+    command: String,
+    cwd: Option<String>,
+    env: Option<HashMap<String, String>>,
+    shell: Option<ShellConfig>,
+    encoding: Option<String>,
+    on_event: Channel<CommandEvent>,
+) -> CommandResponse<u64> {
+    let started = Instant::now();
+    let encoding = OutputEncoding::parse(encoding.as_deref())?;
+    let (mut cmd, program) = shell_command(&command, shell.as_ref(), cwd.as_deref(), env.as_ref())?;
+    isolate_process_group(&mut cmd);
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = tokio::process::Command::from(cmd)
+        .spawn()
+        .map_err(|e| CommandError::spawn(&program, e))?;
+    let id = registry.register(
+        child.id().expect("freshly spawned child has a pid"),
+        child.stdin.take(),
+        encoding.default_input(),
+    );
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stdout = forward(
+        app.clone(),
+        stdout,
+        on_event.clone(),
+        encoding,
+        |chunk| CommandEvent::Stdout { chunk },
+        CommandEvent::StdoutBinary,
+    );
+    let stderr = forward(
+        app.clone(),
+        stderr,
+        on_event.clone(),
+        encoding,
+        |chunk| CommandEvent::Stderr { chunk },
+        CommandEvent::StderrBinary,
+    );
+
+    async_runtime::spawn(async move {
+        let status = child.wait().await.ok();
+        app.state::<ProcessRegistry>().unregister(id);
+        let grace = app
+            .state::<Mutex<CommandDefaults>>()
+            .lock()
+            .unwrap()
+            .background_timeout_ms;
+        drain([stdout, stderr], Duration::from_millis(grace)).await;
+        let _ = on_event.send(CommandEvent::Finished {
+            exit_code: status.and_then(|status| status.code()),
+            signal: status.as_ref().and_then(exit_signal),
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+    });
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn get_command_defaults(
+    defaults: State<'_, Mutex<CommandDefaults>>,
+) -> CommandResponse<CommandDefaults> {
+    Ok(defaults.lock().unwrap().clone())
+}
+
+#[tauri::command]
+pub fn set_command_defaults(
+    defaults: State<'_, Mutex<CommandDefaults>>,
+    value: CommandDefaults,
+) -> CommandResponse<()> {
+    *defaults.lock().unwrap() = value;
+    Ok(())
+}
@@ -0,0 +1,706 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::alias;
+use crate::error::{validate_env, CommandResponse};
+use crate::shell::ShellConfig;
+
+/// Settings from `.quicktermrc`. Anything left out keeps its default;
+/// aliases and keybindings are added to the built-in ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(
+    default,
+    deny_unknown_fields,
+    rename_all(serialize = "camelCase", deserialize = "snake_case")
+)]
+pub struct Config {
+    /// The shell new sessions start, unless a profile says otherwise.
+    pub shell: ShellConfig,
+    /// Set in every session, under the profile's and the caller's.
+    #[serde(deserialize_with = "env")]
+    pub env: HashMap<String, String>,
+    #[serde(deserialize_with = "aliases")]
+    pub aliases: BTreeMap<String, String>,
+    /// Expanded in the input line when space is pressed, so history shows
+    /// the real command.
+    #[serde(deserialize_with = "alias_table")]
+    pub abbreviations: BTreeMap<String, String>,
+    pub theme: ThemeConfig,
+    #[serde(deserialize_with = "keybindings")]
+    pub keybindings: BTreeMap<KeyAction, KeyChord>,
+    pub history: HistoryConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            shell: ShellConfig::default(),
+            env: HashMap::new(),
+            aliases: default_aliases(),
+            abbreviations: BTreeMap::new(),
+            theme: ThemeConfig::default(),
+            keybindings: default_keybindings(),
+            history: HistoryConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(
+    default,
+    deny_unknown_fields,
+    rename_all(serialize = "camelCase", deserialize = "snake_case")
+)]
+pub struct ThemeConfig {
+    pub name: Option<String>,
+    pub background: Option<Color>,
+    pub foreground: Option<Color>,
+    pub font_family: Option<String>,
+    pub font_size: Option<FontSize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(
+    default,
+    deny_unknown_fields,
+    rename_all(serialize = "camelCase", deserialize = "snake_case")
+)]
+pub struct HistoryConfig {
+    /// How many commands are kept; the oldest go first.
+    pub max_entries: usize,
+    /// Keep only the latest run of a repeated command.
+    pub ignore_duplicates: bool,
+    /// Leave commands typed with a leading space out, as bash's
+    /// `HISTCONTROL=ignorespace` does.
+    pub ignore_space: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            max_entries: 100,
+            ignore_duplicates: true,
+            ignore_space: false,
+        }
+    }
+}
+
+/// What a key chord in `[keybindings]` does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyAction {
+    /// Interrupt the running command.
+    Interrupt,
+    /// Send end-of-file to the running command.
+    Eof,
+    Clear,
+    TogglePerformance,
+}
+
+/// A key with modifiers, e.g. "ctrl+shift+k", kept in that normalized form.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct KeyChord(String);
+
+const MODIFIERS: [&str; 4] = ["ctrl", "alt", "shift", "meta"];
+
+const NAMED_KEYS: [&str; 15] = [
+    "enter",
+    "tab",
+    "escape",
+    "space",
+    "backspace",
+    "delete",
+    "insert",
+    "up",
+    "down",
+    "left",
+    "right",
+    "home",
+    "end",
+    "pageup",
+    "pagedown",
+];
+
+impl KeyChord {
+    pub fn parse(chord: &str) -> Result<Self, String> {
+        let lowered = chord.trim().to_lowercase();
+        let mut parts: Vec<&str> = lowered.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        let mut modifiers = Vec::new();
+        for part in parts {
+            let modifier = match part {
+                "control" => "ctrl",
+                "option" => "alt",
+                "cmd" | "super" => "meta",
+                other => other,
+            };
+            if !MODIFIERS.contains(&modifier) {
+                return Err(format!("unknown modifier {part:?} in {chord:?}"));
+            }
+            if !modifiers.contains(&modifier) {
+                modifiers.push(modifier);
+            }
+        }
+        let function_key = key
+            .strip_prefix('f')
+            .and_then(|n| n.parse::<u8>().ok())
+            .is_some_and(|n| (1..=24).contains(&n));
+        if key.chars().count() != 1 && !NAMED_KEYS.contains(&key) && !function_key {
+            return Err(format!("unknown key {key:?} in {chord:?}"));
+        }
+        modifiers.sort_by_key(|modifier| MODIFIERS.iter().position(|m| m == modifier));
+        modifiers.push(key);
+        Ok(KeyChord(modifiers.join("+")))
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        KeyChord::parse(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// A `#rrggbb` colour.
+#[derive(Clone, Debug, Serialize)]
+pub struct Color(String);
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        let valid = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !valid {
+            return Err(D::Error::custom(format!(
+                "expected a colour like \"#1e2a3a\", found {color:?}"
+            )));
+        }
+        Ok(Color(color.to_lowercase()))
+    }
+}
+
+/// Font size in pixels.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct FontSize(f64);
+
+impl<'de> Deserialize<'de> for FontSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let size = f64::deserialize(deserializer)?;
+        if !(6.0..=72.0).contains(&size) {
+            return Err(D::Error::custom(format!(
+                "font size must be between 6 and 72, found {size}"
+            )));
+        }
+        Ok(FontSize(size))
+    }
+}
+
+fn default_aliases() -> BTreeMap<String, String> {
+    [
+        ("ll", "ls -la"),
+        ("la", "ls -la"),
+        ("l", "ls -lh"),
+        ("ls", "ls --color=auto"),
+        ("..", "cd .."),
+        ("...", "cd ../.."),
+        ("....", "cd ../../.."),
+        ("~", "cd ~"),
+        ("-", "cd -"),
+        ("md", "mkdir"),
+        ("rd", "rmdir"),
+        ("cls", "clear"),
+        ("c", "clear"),
+        ("gs", "git status"),
+        ("ga", "git add"),
+        ("gc", "git commit"),
+        ("gp", "git push"),
+        ("gl", "git log"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+fn default_keybindings() -> BTreeMap<KeyAction, KeyChord> {
+    [
+        (KeyAction::Interrupt, "ctrl+c"),
+        (KeyAction::Eof, "ctrl+d"),
+        (KeyAction::Clear, "ctrl+l"),
+        (KeyAction::TogglePerformance, "ctrl+p"),
+    ]
+    .into_iter()
+    .map(|(action, chord)| (action, KeyChord(chord.to_string())))
+    .collect()
+}
+
+pub fn env<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    let env = HashMap::deserialize(deserializer)?;
+    validate_env(&env).map_err(D::Error::custom)?;
+    Ok(env)
+}
+
+/// An `[aliases]` or `[abbreviations]` table, checked but not merged with
+/// the built-in aliases.
+pub fn alias_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let aliases: BTreeMap<AliasName, String> = BTreeMap::deserialize(deserializer)?;
+    Ok(aliases
+        .into_iter()
+        .map(|(AliasName(name), value)| (name, value))
+        .collect())
+}
+
+/// Checked as each key is read, so an error points at the name rather than
+/// the whole table.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct AliasName(String);
+
+impl<'de> Deserialize<'de> for AliasName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if !alias::valid_name(&name) {
+            return Err(D::Error::custom(format!("invalid name {name:?}")));
+        }
+        Ok(AliasName(name))
+    }
+}
+
+fn aliases<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let mut aliases = default_aliases();
+    aliases.extend(alias_table(deserializer)?);
+    Ok(aliases)
+}
+
+fn keybindings<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<KeyAction, KeyChord>, D::Error> {
+    let mut keybindings = default_keybindings();
+    keybindings.extend(BTreeMap::<KeyAction, KeyChord>::deserialize(deserializer)?);
+    Ok(keybindings)
+}
+
+/// Why the config file couldn't be used, pointing at the offending spot.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigError {
+    pub path: String,
+    /// 1-based; missing when the file couldn't be read at all.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "{}:{line}:{column}: {}", self.path, self.message)
+            }
+            _ => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// `$QUICKTERM_CONFIG`, else `.quicktermrc` in the `quickterm` directory of
+/// the XDG config dir (`~/.config` unless `$XDG_CONFIG_HOME` says otherwise).
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("QUICKTERM_CONFIG") {
+        return Some(path.into());
+    }
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            if cfg!(windows) {
+                dirs::config_dir()
+            } else {
+                dirs::home_dir().map(|home| home.join(".config"))
+            }
+        })?;
+    Some(dir.join("quickterm").join(".quicktermrc"))
+}
+
+/// Parses and checks a config file's contents.
+pub fn parse<T: DeserializeOwned>(path: &str, text: &str) -> Result<T, ConfigError> {
+    toml::from_str(text).map_err(|e: toml::de::Error| {
+        let (line, column) = match e.span() {
+            Some(span) => {
+                let (line, column) = line_column(text, span.start);
+                (Some(line), Some(column))
+            }
+            None => (None, None),
+        };
+        ConfigError {
+            path: path.into(),
+            line,
+            column,
+            message: e.message().to_string(),
+        }
+    })
+}
+
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Reads the config file. A missing file is the default config.
+pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    let display = path.display().to_string();
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&display, &text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(ConfigError {
+            path: display,
+            line: None,
+            column: None,
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// The config in effect, and the error if the file couldn't be used (in
+/// which case the last config that could, or the defaults, stay in effect).
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedConfig {
+    pub path: Option<String>,
+    pub config: Config,
+    pub error: Option<ConfigError>,
+    /// Why edits to the file won't be picked up until the app restarts.
+    pub watch_error: Option<ConfigError>,
+}
+
+/// Sent as `config-changed` when an edit to the file has been applied.
+#[derive(Clone, Serialize)]
+pub struct ConfigChanged {
+    config: Config,
+    changes: Vec<ConfigDiff>,
+}
+
+/// One setting that differs, e.g. `["aliases", "gco"]` or
+/// `["theme", "fontSize"]`. `old` or `new` is null when the setting was
+/// added or removed.
+#[derive(Clone, Serialize)]
+pub struct ConfigDiff {
+    key: Vec<String>,
+    old: Option<Value>,
+    new: Option<Value>,
+}
+
+fn diff(old: &Config, new: &Config) -> Vec<ConfigDiff> {
+    let mut old_leaves = BTreeMap::new();
+    let mut new_leaves = BTreeMap::new();
+    flatten(
+        &mut Vec::new(),
+        serde_json::to_value(old).unwrap_or_default(),
+        &mut old_leaves,
+    );
+    flatten(
+        &mut Vec::new(),
+        serde_json::to_value(new).unwrap_or_default(),
+        &mut new_leaves,
+    );
+    let mut changes = Vec::new();
+    for (key, old) in &old_leaves {
+        if new_leaves.get(key) != Some(old) {
+            changes.push(ConfigDiff {
+                key: key.clone(),
+                old: Some(old.clone()),
+                new: new_leaves.get(key).cloned(),
+            });
+        }
+    }
+    for (key, new) in new_leaves {
+        if !old_leaves.contains_key(&key) {
+            changes.push(ConfigDiff {
+                key,
+                old: None,
+                new: Some(new),
+            });
+        }
+    }
+    changes
+}
+
+/// Collects every setting that isn't a table, keyed by its path. Arrays
+/// count as one setting.
+fn flatten(key: &mut Vec<String>, value: Value, out: &mut BTreeMap<Vec<String>, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map {
+                key.push(name);
+                flatten(key, value, out);
+                key.pop();
+            }
+        }
+        leaf => {
+            out.insert(key.clone(), leaf);
+        }
+    }
+}
+
+/// How long the file has to stay quiet before it's read again; editors often
+/// save in several steps.
+const SETTLE: Duration = Duration::from_millis(100);
+
+pub struct ConfigStore {
+    path: Option<PathBuf>,
+    loaded: Mutex<LoadedConfig>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl ConfigStore {
+    pub fn load() -> Self {
+        let path = config_path();
+        let (config, error) = match path.as_deref().map(load) {
+            Some(Ok(config)) => (config, None),
+            Some(Err(error)) => (Config::default(), Some(error)),
+            None => (Config::default(), None),
+        };
+        ConfigStore {
+            loaded: Mutex::new(LoadedConfig {
+                path: path.as_ref().map(|path| path.display().to_string()),
+                config,
+                error,
+                watch_error: None,
+            }),
+            path,
+            watcher: Mutex::new(None),
+        }
+    }
+
+    /// Reads the file again. A good file replaces the config and is announced
+    /// with `config-changed`; a bad one is reported with `config-error` and
+    /// the config in effect is kept.
+    fn reload(&self, app: &AppHandle) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = load(path);
+        let mut loaded = self.loaded.lock().unwrap();
+        match result {
+            Ok(config) => {
+                let changes = diff(&loaded.config, &config);
+                let recovered = loaded.error.take().is_some();
+                if changes.is_empty() && !recovered {
+                    return;
+                }
+                loaded.config = config.clone();
+                drop(loaded);
+                let _ = app.emit("config-changed", ConfigChanged { config, changes });
+            }
+            Err(error) => {
+                loaded.error = Some(error.clone());
+                drop(loaded);
+                let _ = app.emit("config-error", error);
+            }
+        }
+    }
+
+    /// Keeps the reason the file can't be watched for `get_config` to report.
+    pub fn watch_failed(&self, error: notify::Error) {
+        let path = self.path.as_ref().map(|path| path.display().to_string());
+        self.loaded.lock().unwrap().watch_error = Some(ConfigError {
+            path: path.unwrap_or_default(),
+            line: None,
+            column: None,
+            message: error.to_string(),
+        });
+    }
+
+    pub fn config(&self) -> Config {
+        self.loaded.lock().unwrap().config.clone()
+    }
+}
+
+/// Reloads the config whenever its file changes. The directory is watched
+/// rather than the file, since editors often save by replacing it and the
+/// file may not exist yet.
+pub fn watch(app: AppHandle) -> notify::Result<()> {
+    let store = app.state::<ConfigStore>();
+    let Some(path) = store.path.clone() else {
+        return Ok(());
+    };
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+    fs::create_dir_all(dir)?;
+
+    let (tx, rx) = mpsc::channel();
+    let file_name = path.file_name().map(ToOwned::to_owned);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let relevant = !event.kind.is_access()
+            && event
+                .paths
+                .iter()
+                .any(|changed| changed.file_name() == file_name.as_deref());
+        if relevant {
+            let _ = tx.send(());
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    *store.watcher.lock().unwrap() = Some(watcher);
+
+    thread::spawn(move || {
+        while rx.recv().is_ok() {
+            while rx.recv_timeout(SETTLE).is_ok() {}
+            app.state::<ConfigStore>().reload(&app);
+        }
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_config(store: State<'_, ConfigStore>) -> CommandResponse<LoadedConfig> {
+    Ok(store.loaded.lock().unwrap().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(text: &str) -> Config {
+        parse("test.toml", text).unwrap()
+    }
+
+    fn key(path: &[&str]) -> Vec<String> {
+        path.iter().map(|part| part.to_string()).collect()
+    }
+
+    /// Where `parse` places the error in `text`, and its message.
+    fn error_at(text: &str) -> (Option<usize>, Option<usize>, String) {
+        let error = parse::<Config>("test.toml", text).unwrap_err();
+        (error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn locates_unknown_fields() {
+        let (line, column, message) = error_at("[theme]\nname = \"dark\"\nfont_colour = 1\n");
+        assert_eq!((line, column), (Some(3), Some(1)));
+        assert!(message.contains("unknown field `font_colour`"), "{message}");
+    }
+
+    #[test]
+    fn locates_bad_colours() {
+        let (line, column, message) = error_at("[theme]\nbackground = \"#12345\"\n");
+        assert_eq!((line, column), (Some(2), Some(14)));
+        assert!(message.contains("\"#12345\""), "{message}");
+    }
+
+    #[test]
+    fn locates_bad_keybinding_modifiers() {
+        let (line, column, message) =
+            error_at("[keybindings]\nclear = \"ctrl+l\"\ninterrupt = \"hyper+c\"\n");
+        assert_eq!((line, column), (Some(3), Some(13)));
+        assert!(message.contains("unknown modifier \"hyper\""), "{message}");
+    }
+
+    #[test]
+    fn locates_invalid_alias_names() {
+        let (line, column, message) = error_at("[aliases]\n\"g co\" = \"git checkout\"\n");
+        assert_eq!((line, column), (Some(2), Some(1)));
+        assert!(message.contains("invalid name \"g co\""), "{message}");
+    }
+
+    #[test]
+    fn flattens_tables_into_leaves() {
+        let mut out = BTreeMap::new();
+        let value = json!({
+            "a": { "b": 1, "c": { "d": "x" }, "empty": {} },
+            "list": [1, { "e": 2 }],
+            "none": null,
+        });
+        flatten(&mut Vec::new(), value, &mut out);
+        assert_eq!(
+            out,
+            BTreeMap::from([
+                (key(&["a", "b"]), json!(1)),
+                (key(&["a", "c", "d"]), json!("x")),
+                (key(&["list"]), json!([1, { "e": 2 }])),
+                (key(&["none"]), json!(null)),
+            ])
+        );
+    }
+
+    #[test]
+    fn same_config_has_no_changes() {
+        let text = "[aliases]\ngco = \"git checkout\"\n";
+        assert!(diff(&config(text), &config(text)).is_empty());
+        assert!(diff(&Config::default(), &config("")).is_empty());
+    }
+
+    #[test]
+    fn reports_changed_added_and_removed_settings() {
+        let old = config(
+            "[aliases]\ngco = \"git checkout\"\ngb = \"git branch\"\n\
+             [history]\nmax_entries = 100\n",
+        );
+        let new = config(
+            "[aliases]\ngco = \"git checkout -b\"\ngd = \"git diff\"\n\
+             [history]\nmax_entries = 500\n[theme]\nfont_size = 14\n",
+        );
+        let changes: Vec<_> = diff(&old, &new)
+            .into_iter()
+            .map(|change| (change.key, change.old, change.new))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (key(&["aliases", "gb"]), Some(json!("git branch")), None),
+                (
+                    key(&["aliases", "gco"]),
+                    Some(json!("git checkout")),
+                    Some(json!("git checkout -b")),
+                ),
+                (
+                    key(&["history", "maxEntries"]),
+                    Some(json!(100)),
+                    Some(json!(500))
+                ),
+                (
+                    key(&["theme", "fontSize"]),
+                    Some(json!(null)),
+                    Some(json!(14.0))
+                ),
+                (key(&["aliases", "gd"]), None, Some(json!("git diff"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_change_as_a_whole() {
+        let old = config("[shell]\nprogram = \"zsh\"\nargs = [\"-l\"]\n");
+        let new = config("[shell]\nprogram = \"zsh\"\nargs = [\"-l\", \"-i\"]\n");
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].key, key(&["shell", "args"]));
+        assert_eq!(changes[0].new, Some(json!(["-l", "-i"])));
+    }
+}
@@ -0,0 +1,255 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, EncoderResult, Encoding, UTF_8};
+
+use crate::error::{CommandError, CommandResponse};
+
+/// Looks an encoding up by any of its WHATWG labels: "gbk", "shift_jis",
+/// "latin1" and so on.
+pub fn lookup(label: &str) -> CommandResponse<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| CommandError::UnknownEncoding {
+        label: label.into(),
+    })
+}
+
+/// How program output is decoded.
+#[derive(Clone, Copy)]
+pub enum OutputEncoding {
+    /// UTF-8, with output that isn't valid UTF-8 decoded in a guessed legacy
+    /// encoding instead.
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+impl OutputEncoding {
+    /// Parses "auto" or an encoding label; `None` means "auto".
+    pub fn parse(label: Option<&str>) -> CommandResponse<Self> {
+        match label {
+            None | Some("auto") => Ok(OutputEncoding::Auto),
+            Some(label) => lookup(label).map(OutputEncoding::Fixed),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputEncoding::Auto => "auto",
+            OutputEncoding::Fixed(encoding) => encoding.name(),
+        }
+    }
+
+    /// The encoding to send input in when none is given: the output's, or
+    /// UTF-8 when that is detected.
+    pub fn default_input(&self) -> &'static Encoding {
+        match self {
+            OutputEncoding::Auto => UTF_8,
+            OutputEncoding::Fixed(encoding) => encoding.output_encoding(),
+        }
+    }
+}
+
+/// Turns a stream of output into text, carrying characters split between
+/// reads over to the next one.
+pub struct OutputDecoder {
+    encoding: OutputEncoding,
+    /// For `Auto`, the legacy encoding last guessed. It isn't replaced while
+    /// partway through a character.
+    decoder: Decoder,
+    /// For `Auto`, a trailing partial UTF-8 character.
+    pending: Vec<u8>,
+    detector: EncodingDetector,
+}
+
+impl OutputDecoder {
+    pub fn new(encoding: OutputEncoding) -> Self {
+        let initial = match encoding {
+            OutputEncoding::Auto => UTF_8,
+            OutputEncoding::Fixed(encoding) => encoding,
+        };
+        OutputDecoder {
+            encoding,
+            decoder: initial.new_decoder_without_bom_handling(),
+            pending: Vec::new(),
+            detector: EncodingDetector::new(),
+        }
+    }
+
+    pub fn encoding(&self) -> OutputEncoding {
+        self.encoding
+    }
+
+    pub fn decode(&mut self, data: &[u8]) -> String {
+        if let OutputEncoding::Fixed(_) = self.encoding {
+            return decode_with(&mut self.decoder, data, false);
+        }
+        // The rest of a legacy character mustn't be taken for UTF-8.
+        if holds_partial(&self.decoder) {
+            self.detector.feed(data, false);
+            return decode_with(&mut self.decoder, data, false);
+        }
+        self.pending.extend_from_slice(data);
+        if let Some(text) = take_valid_utf8(&mut self.pending) {
+            return text;
+        }
+        // The guess improves as more of the output that isn't UTF-8 is seen.
+        let data = std::mem::take(&mut self.pending);
+        self.detector.feed(&data, false);
+        let guess = self.detector.guess(None, false);
+        if self.decoder.encoding() != guess && !holds_partial(&self.decoder) {
+            self.decoder = guess.new_decoder_without_bom_handling();
+        }
+        decode_with(&mut self.decoder, &data, false)
+    }
+
+    /// Decodes whatever is left at the end of the stream.
+    pub fn finish(&mut self) -> String {
+        let mut text = String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned();
+        text.push_str(&decode_with(&mut self.decoder, &[], true));
+        text
+    }
+}
+
+fn decode_with(decoder: &mut Decoder, data: &[u8], last: bool) -> String {
+    let capacity = decoder
+        .max_utf8_buffer_length(data.len())
+        .unwrap_or(data.len() * 3);
+    let mut text = String::with_capacity(capacity);
+    let _ = decoder.decode_to_string(data, &mut text, last);
+    text
+}
+
+/// Whether `decoder` has the start of a character it's waiting to finish.
+/// encoding_rs doesn't say so directly, but counts those bytes in the space
+/// it asks for.
+fn holds_partial(decoder: &Decoder) -> bool {
+    let fresh = decoder.encoding().new_decoder_without_bom_handling();
+    decoder.max_utf16_buffer_length(0) > fresh.max_utf16_buffer_length(0)
+}
+
+/// Like [`crate::command::take_utf8`], but gives up with `None` if `pending`
+/// isn't UTF-8.
+fn take_valid_utf8(pending: &mut Vec<u8>) -> Option<String> {
+    let valid = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => return None,
+    };
+    let rest = pending.split_off(valid);
+    let text = std::mem::replace(pending, rest);
+    Some(String::from_utf8(text).expect("checked to be UTF-8"))
+}
+
+/// Encodes input for a program expecting `encoding`. Characters it has no
+/// way to represent are sent as `?`.
+pub fn encode_input(encoding: &'static Encoding, text: &str) -> Vec<u8> {
+    if encoding == UTF_8 {
+        return text.as_bytes().to_vec();
+    }
+    let mut encoder = encoding.new_encoder();
+    let mut out = Vec::new();
+    let mut rest = text;
+    loop {
+        let needed = encoder
+            .max_buffer_length_from_utf8_without_replacement(rest.len())
+            .unwrap_or(rest.len() * 4);
+        out.reserve(needed);
+        let (result, read) =
+            encoder.encode_from_utf8_to_vec_without_replacement(rest, &mut out, true);
+        rest = &rest[read..];
+        match result {
+            EncoderResult::InputEmpty => return out,
+            EncoderResult::Unmappable(_) => out.push(b'?'),
+            EncoderResult::OutputFull => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GBK, SHIFT_JIS, WINDOWS_1252};
+
+    /// Decodes `bytes` fed in pieces split at `splits`.
+    fn split_decode(output: OutputEncoding, bytes: &[u8], splits: &[usize]) -> String {
+        let mut decoder = OutputDecoder::new(output);
+        let mut text = String::new();
+        let mut from = 0;
+        for &to in splits.iter().chain([&bytes.len()]) {
+            text.push_str(&decoder.decode(&bytes[from..to]));
+            from = to;
+        }
+        text.push_str(&decoder.finish());
+        text
+    }
+
+    /// Every way of splitting `text` into two reads from byte `from` on, in
+    /// `encoding`.
+    fn assert_splits(output: OutputEncoding, encoding: &'static Encoding, text: &str, from: usize) {
+        let (bytes, _, _) = encoding.encode(text);
+        for at in from..=bytes.len() {
+            assert_eq!(split_decode(output, &bytes, &[at]), text, "split at {at}");
+        }
+    }
+
+    const GBK_TEXT: &str = "这是一段用来测试编码检测的中文文本，丂abc\n后面还有更多的中文内容。\n";
+    const SHIFT_JIS_TEXT: &str =
+        "これは文字コードの判定を試すための日本語の文章です。\nabc\n続きの文章もあります。\n";
+    const LATIN1_TEXT: &str = "Voilà un café très agréable, déjà près de la forêt.\nabc\n";
+
+    #[test]
+    fn fixed_encodings_carry_split_characters() {
+        assert_splits(OutputEncoding::Fixed(GBK), GBK, GBK_TEXT, 0);
+        assert_splits(
+            OutputEncoding::Fixed(SHIFT_JIS),
+            SHIFT_JIS,
+            SHIFT_JIS_TEXT,
+            0,
+        );
+        assert_splits(
+            OutputEncoding::Fixed(WINDOWS_1252),
+            WINDOWS_1252,
+            LATIN1_TEXT,
+            0,
+        );
+    }
+
+    #[test]
+    fn auto_keeps_a_split_legacy_character_together() {
+        // A byte or two is too little to guess from, so split once the first
+        // line has been seen.
+        let from = |encoding: &'static Encoding, text: &str| {
+            encoding.encode(&text[..text.find('\n').unwrap()]).0.len()
+        };
+        for (encoding, text) in [
+            (GBK, GBK_TEXT),
+            (SHIFT_JIS, SHIFT_JIS_TEXT),
+            (WINDOWS_1252, LATIN1_TEXT),
+        ] {
+            assert_splits(OutputEncoding::Auto, encoding, text, from(encoding, text));
+        }
+    }
+
+    #[test]
+    fn auto_splits_legacy_text_anywhere_once_detected() {
+        // `丂` is 0x81 0x40, and 0x40 alone is `@`.
+        let (bytes, _, _) = GBK.encode(GBK_TEXT);
+        let split = GBK_TEXT.find('丂').unwrap();
+        let at = GBK.encode(&GBK_TEXT[..split]).0.len() + 1;
+        assert_eq!(bytes[at - 1..at + 1], [0x81, 0x40]);
+        assert_eq!(
+            split_decode(OutputEncoding::Auto, &bytes, &[at, at + 4, at + 10]),
+            GBK_TEXT
+        );
+    }
+
+    #[test]
+    fn auto_decodes_utf8() {
+        assert_splits(OutputEncoding::Auto, UTF_8, "héllo 世界 😀\n", 0);
+    }
+
+    #[test]
+    fn encodes_input() {
+        assert_eq!(encode_input(GBK, "丂a"), [0x81, 0x40, b'a']);
+        assert_eq!(encode_input(SHIFT_JIS, "日本"), [0x93, 0xfa, 0x96, 0x7b]);
+        assert_eq!(encode_input(WINDOWS_1252, "é€☃"), [0xe9, 0x80, b'?']);
+        assert_eq!(encode_input(UTF_8, "é"), "é".as_bytes());
+    }
+}
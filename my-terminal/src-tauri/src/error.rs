@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::command::CommandResult;
+
+/// Error returned by every Tauri command. Serialized as `{ kind, ...fields }`
+/// so the frontend can branch on `kind` instead of parsing messages.
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all_fields = "camelCase")]
+pub enum CommandError {
+    #[error("failed to start {program}: {message}")]
+    SpawnFailed { program: String, message: String },
+    #[error("{program}: command not found")]
+    NotFound { program: String },
+    #[error("{path}: permission denied")]
+    PermissionDenied { path: String },
+    #[error("process exited with {}", exit_description(.0))]
+    NonZeroExit(Box<CommandResult>),
+    #[error("{path}: not a directory")]
+    InvalidCwd { path: String },
+    #[error("invalid environment variable {key:?}")]
+    InvalidEnv { key: String },
+    #[error("unknown encoding {label:?}")]
+    UnknownEncoding { label: String },
+    #[error("command timed out after {after_ms}ms")]
+    Timeout { after_ms: u64 },
+    #[error("command was cancelled")]
+    Cancelled,
+    #[error("no profile named {name:?}")]
+    UnknownProfile { name: String },
+    #[error("invalid profile name {name:?}")]
+    InvalidProfileName { name: String },
+    #[error("alias loop: {}", chain.join(" -> "))]
+    AliasCycle { chain: Vec<String> },
+    #[error("invalid alias {name:?}")]
+    InvalidAlias { name: String },
+    #[error("no alias named {name:?}")]
+    UnknownAlias { name: String },
+    #[error("{program} does not support running commands in a session")]
+    NoShellIntegration { program: String },
+    #[error("no running process with id {id}")]
+    UnknownId { id: u64 },
+    #[error("stdin of process {id} is closed")]
+    StdinClosed { id: u64 },
+    #[error("{message}")]
+    Io { message: String },
+}
+
+pub type CommandResponse<T> = Result<T, CommandError>;
+
+fn exit_description(result: &CommandResult) -> String {
+    match (result.exit_code, result.signal) {
+        (Some(code), _) => format!("status {code}"),
+        (None, Some(signal)) => format!("signal {signal}"),
+        (None, None) => "unknown status".into(),
+    }
+}
+
+impl CommandError {
+    /// Classifies a failure to launch `program`.
+    pub fn spawn(program: &str, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => CommandError::NotFound {
+                program: program.into(),
+            },
+            io::ErrorKind::PermissionDenied => CommandError::PermissionDenied {
+                path: program.into(),
+            },
+            _ => CommandError::SpawnFailed {
+                program: program.into(),
+                message: err.to_string(),
+            },
+        }
+    }
+
+    pub fn io(err: impl ToString) -> Self {
+        CommandError::Io {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Checks that `cwd` is an absolute path to an existing directory the child
+/// can start in.
+pub fn validate_cwd(cwd: &str) -> CommandResponse<()> {
+    let path = Path::new(cwd);
+    if !path.is_absolute() {
+        return Err(CommandError::InvalidCwd { path: cwd.into() });
+    }
+    match path.metadata() {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            Err(CommandError::PermissionDenied { path: cwd.into() })
+        }
+        _ => Err(CommandError::InvalidCwd { path: cwd.into() }),
+    }
+}
+
+/// Rejects names and values the OS would refuse or silently truncate.
+pub fn validate_env(env: &HashMap<String, String>) -> CommandResponse<()> {
+    for (key, value) in env {
+        if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+            return Err(CommandError::InvalidEnv { key: key.clone() });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn classifies_spawn_errors() {
+        let not_found = io::Error::from(io::ErrorKind::NotFound);
+        assert!(matches!(
+            CommandError::spawn("nope", not_found),
+            CommandError::NotFound { program } if program == "nope"
+        ));
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            CommandError::spawn("./script", denied),
+            CommandError::PermissionDenied { path } if path == "./script"
+        ));
+
+        let other = io::Error::other("exec format error");
+        assert!(matches!(
+            CommandError::spawn("a.out", other),
+            CommandError::SpawnFailed { program, message }
+                if program == "a.out" && message == "exec format error"
+        ));
+    }
+
+    #[test]
+    fn validates_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let path = |path: &Path| path.to_str().unwrap().to_string();
+
+        assert!(validate_cwd(&path(dir.path())).is_ok());
+        assert!(matches!(
+            validate_cwd("relative/dir"),
+            Err(CommandError::InvalidCwd { path }) if path == "relative/dir"
+        ));
+        assert!(matches!(
+            validate_cwd(&path(&dir.path().join("missing"))),
+            Err(CommandError::InvalidCwd { .. })
+        ));
+        assert!(matches!(
+            validate_cwd(&path(&file)),
+            Err(CommandError::InvalidCwd { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validates_unreadable_cwd() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        let inner = locked.join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let result = validate_cwd(inner.to_str().unwrap());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o700)).unwrap();
+        // Root can look inside anyway.
+        if unsafe { libc::geteuid() } == 0 {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(result, Err(CommandError::PermissionDenied { .. })));
+        }
+    }
+
+    #[test]
+    fn validates_env() {
+        let env = |key: &str, value: &str| HashMap::from([(key.to_string(), value.to_string())]);
+
+        assert!(validate_env(&env("PATH", "/bin:/usr/bin")).is_ok());
+        assert!(validate_env(&env("EMPTY", "")).is_ok());
+        for (key, value) in [("", "x"), ("A=B", "x"), ("A\0B", "x"), ("A", "x\0y")] {
+            assert!(
+                matches!(validate_env(&env(key, value)), Err(CommandError::InvalidEnv { key: k }) if k == key),
+                "{key:?}={value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn serializes_kind_and_camel_case_fields() {
+        let json = |err: CommandError| serde_json::to_value(err).unwrap();
+
+        assert_eq!(
+            json(CommandError::Timeout { after_ms: 5000 }),
+            json!({ "kind": "Timeout", "afterMs": 5000 })
+        );
+        assert_eq!(
+            json(CommandError::Cancelled),
+            json!({ "kind": "Cancelled" })
+        );
+        assert_eq!(
+            json(CommandError::SpawnFailed {
+                program: "zsh".into(),
+                message: "boom".into(),
+            }),
+            json!({ "kind": "SpawnFailed", "program": "zsh", "message": "boom" })
+        );
+        assert_eq!(
+            json(CommandError::NonZeroExit(Box::new(CommandResult {
+                stdout: "out".into(),
+                stderr: "err".into(),
+                stdout_binary: None,
+                stderr_binary: None,
+                exit_code: Some(2),
+                signal: None,
+                duration_ms: 12,
+                started_at: 1_700_000_000_000,
+            }))),
+            json!({
+                "kind": "NonZeroExit",
+                "stdout": "out",
+                "stderr": "err",
+                "stdoutBinary": null,
+                "stderrBinary": null,
+                "exitCode": 2,
+                "signal": null,
+                "durationMs": 12,
+                "startedAt": 1_700_000_000_000u64,
+            })
+        );
+    }
+}
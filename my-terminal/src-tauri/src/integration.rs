@@ -0,0 +1,425 @@
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::shell::shell_name;
+
+// Every integration ends each command by reporting the shell's working
+// directory (OSC 7) followed by the command's exit status (OSC 133;D). Echo and
+// prompts are switched off because the frontend draws its own prompt and
+// already shows what was typed.
+//
+// Each marker is prefixed with the session's nonce, written in place of
+// `@NONCE@`, so a program printing the same sequences can't end a command or
+// move the cwd.
+
+const BASH_RC: &str = r#"if [ -f ~/.bashrc ]; then . ~/.bashrc; fi
+set +o emacs +o vi
+stty -echo 2>/dev/null
+__quickterm_prompt() {
+    local ret=$?
+    printf '\033]@NONCE@;7;file://%s%s\007\033]@NONCE@;133;D;%s\007' "$HOSTNAME" "$PWD" "$ret"
+    return $ret
+}
+PROMPT_COMMAND="__quickterm_prompt${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+PS1=''
+PS2=''
+"#;
+
+const ZSH_ENV: &str = r#"if [ -f "${QUICKTERM_ZDOTDIR:-$HOME}/.zshenv" ]; then . "${QUICKTERM_ZDOTDIR:-$HOME}/.zshenv"; fi
+"#;
+
+const ZSH_RC: &str = r#"if [ -n "$QUICKTERM_ZDOTDIR" ]; then ZDOTDIR=$QUICKTERM_ZDOTDIR; else unset ZDOTDIR; fi
+unset QUICKTERM_ZDOTDIR
+if [ -f "${ZDOTDIR:-$HOME}/.zshrc" ]; then . "${ZDOTDIR:-$HOME}/.zshrc"; fi
+unsetopt zle
+stty -echo 2>/dev/null
+__quickterm_precmd() {
+    local ret=$?
+    printf '\033]@NONCE@;7;file://%s%s\007\033]@NONCE@;133;D;%s\007' "$HOST" "$PWD" "$ret"
+}
+precmd_functions=(__quickterm_precmd $precmd_functions)
+PS1=''
+PS2=''
+RPROMPT=''
+"#;
+
+// POSIX shells only expand parameters in PS1, so the markers live there.
+const POSIX_RC: &str = r#"stty -echo 2>/dev/null
+PS1="$(printf '\033]@NONCE@;7;file://')\$PWD$(printf '\007\033]@NONCE@;133;D;')\$?$(printf '\007')"
+PS2=''
+"#;
+
+const FISH_INIT: &str = r#"stty -echo 2>/dev/null
+set -g fish_greeting ''
+function fish_prompt
+    set -l ret $status
+    printf '\033]@NONCE@;7;file://%s%s\007\033]@NONCE@;133;D;%s\007' $hostname $PWD $ret
+end
+function fish_right_prompt; end
+function fish_mode_prompt; end
+"#;
+
+// Nushell marks prompts itself when `shell_integration.osc133` is on, which
+// would report every command twice.
+const NU_INIT: &str = r#"try { $env.config.shell_integration.osc133 = false }
+$env.config.show_banner = false
+$env.config.hooks.pre_prompt = ($env.config.hooks.pre_prompt? | default [] | append {||
+    print -n $"\e]@NONCE@;7;file://(sys host | get hostname)($env.PWD)\a\e]@NONCE@;133;D;($env.LAST_EXIT_CODE)\a"
+})
+$env.PROMPT_COMMAND = {|| "" }
+$env.PROMPT_COMMAND_RIGHT = {|| "" }
+$env.PROMPT_INDICATOR = {|| "" }
+$env.PROMPT_MULTILINE_INDICATOR = {|| "" }
+"#;
+
+/// Launch arguments and environment that make a shell report command
+/// completion. Owns a temporary directory holding the generated rc files.
+pub struct ShellIntegration {
+    /// Private to the user and freshly created, so nobody else can swap the
+    /// rc files before the shell reads them. Removed on drop.
+    _dir: TempDir,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    /// Marks the sequences that really come from the scripts.
+    pub nonce: String,
+}
+
+impl ShellIntegration {
+    /// Returns `None` for shells we don't know how to integrate with.
+    pub fn prepare(program: &str) -> io::Result<Option<ShellIntegration>> {
+        let name = shell_name(program);
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("quickterm-");
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700));
+        let tmp = builder.tempdir()?;
+        let dir = tmp.path();
+        let nonce = nonce();
+        let script = |template: &str| template.replace("@NONCE@", &nonce);
+
+        let (args, env) = match name {
+            "bash" => {
+                let rc = write_file(dir, "bashrc", &script(BASH_RC))?;
+                (vec!["--rcfile".into(), rc, "-i".into()], vec![])
+            }
+            "zsh" => {
+                write_file(dir, ".zshenv", ZSH_ENV)?;
+                write_file(dir, ".zshrc", &script(ZSH_RC))?;
+                let user_zdotdir = std::env::var("ZDOTDIR").unwrap_or_default();
+                (
+                    vec!["-i".into()],
+                    vec![
+                        ("ZDOTDIR".into(), dir.to_string_lossy().into_owned()),
+                        ("QUICKTERM_ZDOTDIR".into(), user_zdotdir),
+                    ],
+                )
+            }
+            "sh" | "dash" | "ash" | "ksh" | "mksh" => {
+                let rc = write_file(dir, "shrc", &script(POSIX_RC))?;
+                (vec!["-i".into()], vec![("ENV".into(), rc)])
+            }
+            // fish and nu always run their own line editor, so what is typed
+            // is echoed back with the output.
+            "fish" => {
+                let init = write_file(dir, "init.fish", &script(FISH_INIT))?;
+                let source = format!(
+                    "source '{}'",
+                    init.replace('\\', "\\\\").replace('\'', "\\'")
+                );
+                (vec!["--init-command".into(), source, "-i".into()], vec![])
+            }
+            // In a file rather than the arguments, which anyone can list.
+            "nu" => {
+                let init = write_file(dir, "init.nu", &script(NU_INIT))?;
+                let source = format!("source r#'{init}'#");
+                (vec!["--execute".into(), source], vec![])
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(ShellIntegration {
+            _dir: tmp,
+            args,
+            env,
+            nonce,
+        }))
+    }
+}
+
+/// 128 random bits in hex. `RandomState` is keyed from the OS's random
+/// source, which saves a dependency.
+fn nonce() -> String {
+    let half = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", half(), half())
+}
+
+fn write_file(dir: &Path, name: &str, contents: &str) -> io::Result<String> {
+    let path = dir.join(name);
+    fs::write(&path, contents)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// A terminal event the integration scripts emit.
+#[derive(Debug, PartialEq)]
+pub enum Marker {
+    CommandFinished(i32),
+    Cwd(String),
+}
+
+impl Marker {
+    /// Interprets the payload of an OSC sequence (the bytes between `ESC ]`
+    /// and the terminator). Only payloads starting with the session's `nonce`
+    /// are markers.
+    pub fn parse(payload: &[u8], nonce: &str) -> Option<Marker> {
+        let payload = std::str::from_utf8(payload).ok()?;
+        let payload = payload.strip_prefix(nonce)?.strip_prefix(';')?;
+        if let Some(status) = payload.strip_prefix("133;D;") {
+            return status.parse().ok().map(Marker::CommandFinished);
+        }
+        let url = payload.strip_prefix("7;file://")?;
+        let path = &url[url.find('/')?..];
+        Some(Marker::Cwd(percent_decode(path)))
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const MAX_OSC_LEN: usize = 4096;
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+pub fn encode_osc(payload: &[u8], out: &mut Vec<u8>) {
+    out.extend([ESC, b']']);
+    out.extend_from_slice(payload);
+    out.push(BEL);
+}
+
+pub enum Piece<'a> {
+    Text(&'a [u8]),
+    Osc(&'a [u8]),
+}
+
+/// Splits PTY output into plain bytes and complete OSC payloads, carrying
+/// partial sequences over from one read to the next.
+#[derive(Default)]
+pub struct OscScanner {
+    state: ScanState,
+    osc: Vec<u8>,
+}
+
+impl OscScanner {
+    pub fn feed(&mut self, data: &[u8], mut emit: impl FnMut(Piece<'_>)) {
+        let mut text = Vec::with_capacity(data.len());
+        for &byte in data {
+            match (self.state, byte) {
+                (ScanState::Ground, ESC) => self.state = ScanState::Escape,
+                (ScanState::Ground, _) => text.push(byte),
+                (ScanState::Escape, b']') => {
+                    self.osc.clear();
+                    self.state = ScanState::Osc;
+                }
+                (ScanState::Escape, ESC) => text.push(ESC),
+                (ScanState::Escape, _) => {
+                    text.extend([ESC, byte]);
+                    self.state = ScanState::Ground;
+                }
+                (ScanState::Osc, BEL) => {
+                    self.finish_osc(&mut text, &mut emit);
+                }
+                (ScanState::Osc, ESC) => self.state = ScanState::OscEscape,
+                (ScanState::Osc, _) if self.osc.len() < MAX_OSC_LEN => self.osc.push(byte),
+                (ScanState::Osc, _) => {
+                    // Runaway sequence; give the bytes back as ordinary output.
+                    text.extend([ESC, b']']);
+                    text.append(&mut self.osc);
+                    text.push(byte);
+                    self.state = ScanState::Ground;
+                }
+                (ScanState::OscEscape, b'\\') => {
+                    self.finish_osc(&mut text, &mut emit);
+                }
+                // ESC without `\` cancels the OSC and starts a new escape.
+                (ScanState::OscEscape, b']') => {
+                    self.osc.clear();
+                    self.state = ScanState::Osc;
+                }
+                (ScanState::OscEscape, ESC) => self.state = ScanState::Escape,
+                (ScanState::OscEscape, _) => {
+                    text.extend([ESC, byte]);
+                    self.state = ScanState::Ground;
+                }
+            }
+        }
+        if !text.is_empty() {
+            emit(Piece::Text(&text));
+        }
+    }
+
+    fn finish_osc(&mut self, text: &mut Vec<u8>, emit: &mut impl FnMut(Piece<'_>)) {
+        if !text.is_empty() {
+            emit(Piece::Text(text));
+            text.clear();
+        }
+        emit(Piece::Osc(&self.osc));
+        self.osc.clear();
+        self.state = ScanState::Ground;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Scanned {
+        Text(Vec<u8>),
+        Osc(Vec<u8>),
+    }
+
+    /// Feeds `data` in pieces split at `splits`, joining adjacent text.
+    fn scan(data: &[u8], splits: &[usize]) -> Vec<Scanned> {
+        let mut scanner = OscScanner::default();
+        let mut out = Vec::new();
+        let mut from = 0;
+        for &to in splits.iter().chain([&data.len()]) {
+            scanner.feed(&data[from..to], |piece| match (piece, out.last_mut()) {
+                (Piece::Text(text), Some(Scanned::Text(last))) => last.extend_from_slice(text),
+                (Piece::Text(text), _) => out.push(Scanned::Text(text.to_vec())),
+                (Piece::Osc(payload), _) => out.push(Scanned::Osc(payload.to_vec())),
+            });
+            from = to;
+        }
+        out
+    }
+
+    fn text(text: &str) -> Scanned {
+        Scanned::Text(text.as_bytes().to_vec())
+    }
+
+    fn osc(payload: &str) -> Scanned {
+        Scanned::Osc(payload.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn scans_sequences_split_anywhere() {
+        let data = b"a\x1b]7;file://h/x\x07b\x1b]133;D;0\x1b\\c\x1b[1md";
+        let expected = [
+            text("a"),
+            osc("7;file://h/x"),
+            text("b"),
+            osc("133;D;0"),
+            text("c\x1b[1md"),
+        ];
+        assert_eq!(scan(data, &[]), expected);
+        for at in 0..=data.len() {
+            assert_eq!(scan(data, &[at]), expected, "split at {at}");
+        }
+        let every_byte: Vec<usize> = (1..data.len()).collect();
+        assert_eq!(scan(data, &every_byte), expected);
+    }
+
+    #[test]
+    fn escapes_inside_an_osc_restart_it() {
+        assert_eq!(scan(b"\x1b]bad\x1b]7;ok\x07", &[5]), [osc("7;ok")]);
+        assert_eq!(scan(b"\x1b]bad\x1bxy", &[]), [text("\x1bxy")]);
+        assert_eq!(scan(b"\x1b\x1b]2;t\x07", &[1]), [text("\x1b"), osc("2;t")]);
+    }
+
+    #[test]
+    fn gives_back_runaway_sequences() {
+        let mut data = b"\x1b]".to_vec();
+        data.resize(data.len() + MAX_OSC_LEN + 10, b'x');
+        data.push(BEL);
+        match scan(&data, &[MAX_OSC_LEN / 2]).as_slice() {
+            [Scanned::Text(text)] => assert_eq!(*text, data),
+            other => panic!("expected text, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn only_accepts_markers_with_the_nonce() {
+        let parse = |payload: &str| Marker::parse(payload.as_bytes(), "abc123");
+        assert_eq!(parse("abc123;133;D;2"), Some(Marker::CommandFinished(2)));
+        assert_eq!(
+            parse("abc123;7;file://host/tmp"),
+            Some(Marker::Cwd("/tmp".into()))
+        );
+        assert_eq!(parse("133;D;0"), None);
+        assert_eq!(parse("7;file://host/etc"), None);
+        assert_eq!(parse("abc12;133;D;0"), None);
+        assert_eq!(parse("abc1234;133;D;0"), None);
+        assert_eq!(parse("abc123;133;D;x"), None);
+        assert_eq!(parse("abc123;2;title"), None);
+    }
+
+    #[test]
+    fn decodes_percent_encoded_paths() {
+        let cwd = |url: &str| Marker::parse(format!("n;7;{url}").as_bytes(), "n");
+        assert_eq!(
+            cwd("file://host/a%20b/%E4%B8%AD%e6%96%87"),
+            Some(Marker::Cwd("/a b/中文".into()))
+        );
+        assert_eq!(cwd("file:///tmp/%"), Some(Marker::Cwd("/tmp/%".into())));
+        assert_eq!(cwd("file:///tmp/%4"), Some(Marker::Cwd("/tmp/%4".into())));
+        assert_eq!(
+            cwd("file:///tmp/%zz1"),
+            Some(Marker::Cwd("/tmp/%zz1".into()))
+        );
+        assert_eq!(cwd("file:///%25"), Some(Marker::Cwd("/%".into())));
+        assert_eq!(cwd("file://host"), None);
+        assert_eq!(percent_decode("%ff"), "\u{fffd}");
+    }
+
+    #[test]
+    fn scripts_carry_the_nonce() {
+        for shell in ["bash", "zsh", "sh", "fish", "nu"] {
+            let integration = ShellIntegration::prepare(shell).unwrap().unwrap();
+            assert_eq!(integration.nonce.len(), 32);
+            let mut found = false;
+            for entry in fs::read_dir(integration._dir.path()).unwrap() {
+                let script = fs::read_to_string(entry.unwrap().path()).unwrap();
+                assert!(!script.contains("@NONCE@"), "{shell}");
+                found |= script.contains(&format!("]{};133;D;", integration.nonce));
+            }
+            assert!(found, "{shell}");
+        }
+        let a = ShellIntegration::prepare("bash").unwrap().unwrap();
+        let b = ShellIntegration::prepare("bash").unwrap().unwrap();
+        assert_ne!(a.nonce, b.nonce);
+    }
+}
@@ -1,38 +1,81 @@
-use std::process::Command;
+mod abbreviation;
+mod alias;
+mod binary;
+mod command;
+mod config;
+mod encoding;
+mod error;
+mod integration;
+mod process;
+mod profile;
+mod project;
+mod pty;
+mod scrollback;
+mod session;
+mod shell;
+mod terminal;
 
-#[tauri::command]
-fn execute_command(command: String) -> Result<String, String> {
-    let (shell, shell_arg) = if cfg!(windows) {
-        ("cmd", "/C")
-    } else {
-        ("sh", "-c")
-    };
-    
-    let output = Command::new(shell)
-        .arg(shell_arg)
-        .arg(&command)
-        .output()
-        .map_err(|e| e.to_string())?;
-        
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    
-    if output.status.success() {
-        Ok(stdout.to_string())
-    } else {
-        if !stderr.is_empty() {
-             Err(stderr.to_string())
-        } else {
-             Err(stdout.to_string())
-        }
-    }
-}
+use std::sync::Mutex;
+
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![execute_command])
+        .manage(process::ProcessRegistry::default())
+        .manage(session::SessionManager::default())
+        .manage(binary::BinaryOutputs::default())
+        .manage(Mutex::new(command::CommandDefaults::default()))
+        .manage(config::ConfigStore::load())
+        .setup(|app| {
+            let dir = app.path().app_config_dir()?;
+            app.manage(profile::ProfileStore::load(dir.join("profiles.json")));
+            app.manage(project::TrustStore::load(dir.join("trusted-projects.json")));
+            // Without a watcher the config is still read at startup.
+            if let Err(error) = config::watch(app.handle().clone()) {
+                app.state::<config::ConfigStore>().watch_failed(error);
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            command::execute_command,
+            command::execute_command_stream,
+            command::get_command_defaults,
+            command::set_command_defaults,
+            binary::save_binary_output,
+            binary::discard_binary_output,
+            process::reserve_command_id,
+            process::cancel_command,
+            process::write_stdin,
+            process::close_stdin,
+            pty::pty_spawn,
+            pty::pty_write,
+            pty::pty_resize,
+            pty::pty_close,
+            session::create_session,
+            session::run_in_session,
+            session::write_session,
+            session::resize_session,
+            session::set_session_encoding,
+            session::get_session,
+            session::get_screen,
+            session::get_scrollback,
+            session::search_scrollback,
+            session::close_session,
+            session::check_project,
+            session::trust_project,
+            profile::list_profiles,
+            profile::save_profile,
+            profile::delete_profile,
+            profile::open_profile,
+            alias::expand_aliases,
+            alias::list_aliases,
+            alias::define_alias,
+            alias::remove_alias,
+            abbreviation::expand_abbreviation,
+            config::get_config
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use encoding_rs::Encoding;
+use serde::Deserialize;
+use tauri::{AppHandle, Manager, State};
+use tokio::io::AsyncWriteExt;
+use tokio::process::ChildStdin;
+
+use crate::encoding::encode_input;
+use crate::error::{CommandError, CommandResponse};
+
+/// Children spawned by the command layer, keyed by the id handed to the
+/// frontend. Each child leads its own process group so signals reach
+/// everything it started (pipelines, `npm` scripts, ...).
+#[derive(Default)]
+pub struct ProcessRegistry {
+    next_id: AtomicU64,
+    processes: Mutex<HashMap<u64, RunningProcess>>,
+}
+
+/// Shared so a slow write doesn't hold the registry lock; `None` once the
+/// frontend has closed stdin.
+type SharedStdin = Arc<tokio::sync::Mutex<Option<ChildStdin>>>;
+
+struct RunningProcess {
+    pid: u32,
+    stdin: SharedStdin,
+    /// What `write_stdin` encodes text in.
+    encoding: &'static Encoding,
+}
+
+impl ProcessRegistry {
+    /// An id no process has been given yet.
+    pub fn reserve(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn register(
+        &self,
+        pid: u32,
+        stdin: Option<ChildStdin>,
+        encoding: &'static Encoding,
+    ) -> u64 {
+        let id = self.reserve();
+        self.register_as(id, pid, stdin, encoding);
+        id
+    }
+
+    /// Registers under an id from [`ProcessRegistry::reserve`], which the
+    /// caller may already have handed out.
+    pub fn register_as(
+        &self,
+        id: u64,
+        pid: u32,
+        stdin: Option<ChildStdin>,
+        encoding: &'static Encoding,
+    ) {
+        let process = RunningProcess {
+            pid,
+            stdin: Arc::new(tokio::sync::Mutex::new(stdin)),
+            encoding,
+        };
+        self.processes.lock().unwrap().insert(id, process);
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.processes.lock().unwrap().remove(&id);
+    }
+
+    fn pid(&self, id: u64) -> Option<u32> {
+        self.processes
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|process| process.pid)
+    }
+
+    fn stdin(&self, id: u64) -> CommandResponse<(SharedStdin, &'static Encoding)> {
+        self.processes
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|process| (process.stdin.clone(), process.encoding))
+            .ok_or(CommandError::UnknownId { id })
+    }
+}
+
+/// Puts the child in a new process group whose id equals its pid.
+pub fn isolate_process_group(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+#[derive(Clone, Copy)]
+pub enum Stage {
+    Interrupt,
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+pub fn signal_group(pid: u32, stage: Stage) {
+    let signal = match stage {
+        Stage::Interrupt => libc::SIGINT,
+        Stage::Terminate => libc::SIGTERM,
+        Stage::Kill => libc::SIGKILL,
+    };
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), signal);
+    }
+}
+
+#[cfg(windows)]
+pub fn signal_group(pid: u32, _stage: Stage) {
+    // Windows has no signals to escalate through; take the whole tree down.
+    let _ = Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .output();
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CancelOptions {
+    pub interrupt_grace_ms: u64,
+    pub terminate_grace_ms: u64,
+}
+
+impl Default for CancelOptions {
+    fn default() -> Self {
+        CancelOptions {
+            interrupt_grace_ms: 2000,
+            terminate_grace_ms: 3000,
+        }
+    }
+}
+
+/// An id to pass to `execute_command`, so the command can be cancelled
+/// before its result comes back.
+#[tauri::command]
+pub fn reserve_command_id(registry: State<'_, ProcessRegistry>) -> CommandResponse<u64> {
+    Ok(registry.reserve())
+}
+
+#[tauri::command]
+pub fn cancel_command(
+    app: AppHandle,
+    registry: State<'_, ProcessRegistry>,
+    id: u64,
+    options: Option<CancelOptions>,
+) -> CommandResponse<()> {
+    let pid = registry.pid(id).ok_or(CommandError::UnknownId { id })?;
+    let options = options.unwrap_or_default();
+
+    signal_group(pid, Stage::Interrupt);
+
+    tauri::async_runtime::spawn(async move {
+        let registry = app.state::<ProcessRegistry>();
+        for (grace, stage) in [
+            (options.interrupt_grace_ms, Stage::Terminate),
+            (options.terminate_grace_ms, Stage::Kill),
+        ] {
+            tokio::time::sleep(Duration::from_millis(grace)).await;
+            // Stop escalating once the waiter has reaped the child.
+            if registry.pid(id) != Some(pid) {
+                return;
+            }
+            signal_group(pid, stage);
+        }
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn write_stdin(
+    registry: State<'_, ProcessRegistry>,
+    id: u64,
+    data: String,
+) -> CommandResponse<()> {
+    let (stdin, encoding) = registry.stdin(id)?;
+    let mut stdin = stdin.lock().await;
+    let pipe = stdin.as_mut().ok_or(CommandError::StdinClosed { id })?;
+    pipe.write_all(&encode_input(encoding, &data))
+        .await
+        .map_err(CommandError::io)?;
+    pipe.flush().await.map_err(CommandError::io)
+}
+
+/// Closes the child's stdin so programs reading until EOF can finish.
+#[tauri::command]
+pub async fn close_stdin(registry: State<'_, ProcessRegistry>, id: u64) -> CommandResponse<()> {
+    let (stdin, _) = registry.stdin(id)?;
+    let closed = stdin.lock().await.take();
+    closed.map(drop).ok_or(CommandError::StdinClosed { id })
+}
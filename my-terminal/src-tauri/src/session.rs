@@ -0,0 +1,899 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use encoding_rs::Encoding;
+use serde::Serialize;
+use tauri::ipc::{Channel, Response};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Notify;
+
+use crate::alias::{Alias, AliasSource};
+use crate::binary::{BinaryFilter, BinaryOutputs, BinarySummary};
+use crate::command::{unix_millis, CommandDefaults};
+use crate::config::{Config, ConfigError, ConfigStore};
+use crate::encoding::{encode_input, lookup, OutputDecoder, OutputEncoding};
+use crate::error::{validate_env, CommandError, CommandResponse};
+use crate::integration::{encode_osc, Marker, OscScanner, Piece, ShellIntegration};
+use crate::process::{signal_group, Stage};
+use crate::project::{
+    self, export_command, unset_command, ProjectFile, ProjectStatus, Trust, TrustStore,
+};
+use crate::pty::{home_dir, Pty};
+use crate::scrollback::{self, Scrollback, ScrollbackRange};
+use crate::shell::{shell_name, ShellConfig};
+use crate::terminal::{ScreenSnapshot, Terminal};
+
+const DEFAULT_COLS: u16 = 80;
+const DEFAULT_ROWS: u16 = 24;
+
+/// A long-lived shell on a PTY. The shell, not the frontend, owns the working
+/// directory and environment, so `cd`, `export`, functions and `set` persist
+/// from one command to the next.
+pub struct Session {
+    program: String,
+    /// Arguments chosen for the shell, without the integration's.
+    args: Vec<String>,
+    pty: Mutex<Pty>,
+    env: HashMap<String, String>,
+    state: Mutex<SessionState>,
+    changed: Notify,
+    run_lock: tokio::sync::Mutex<()>,
+    integration: Option<ShellIntegration>,
+    /// Emulated screen contents, for programs that draw rather than print.
+    terminal: Mutex<Terminal>,
+    scrollback: Mutex<Scrollback>,
+    encoding: Mutex<SessionEncoding>,
+    /// The profile the session was opened from, and what it asked for.
+    profile: Option<String>,
+    title: Option<String>,
+    theme: Option<String>,
+    project: Mutex<ProjectState>,
+    /// The profile's aliases.
+    aliases: BTreeMap<String, String>,
+    /// Aliases defined (`Some`) or removed (`None`) with the builtins.
+    alias_overrides: Mutex<BTreeMap<String, Option<String>>>,
+    /// Gets the output exactly as read, for a frontend that emulates the
+    /// terminal itself.
+    raw_output: Option<Channel<Response>>,
+}
+
+#[derive(Default)]
+struct ProjectState {
+    /// The project of the shell's directory when last checked.
+    current: Option<ProjectStatus>,
+    /// Whether `current` has been applied to the shell.
+    applied: bool,
+    /// Env the project set, to undo on leaving it.
+    exported: Vec<String>,
+}
+
+struct SessionEncoding {
+    output: OutputDecoder,
+    input: &'static Encoding,
+}
+
+impl SessionEncoding {
+    fn parse(output: Option<&str>, input: Option<&str>) -> CommandResponse<Self> {
+        let output = OutputEncoding::parse(output)?;
+        let input = match input {
+            Some(label) => lookup(label)?,
+            None => output.default_input(),
+        };
+        Ok(SessionEncoding {
+            output: OutputDecoder::new(output),
+            input,
+        })
+    }
+}
+
+#[derive(Default)]
+struct SessionState {
+    cwd: String,
+    /// Bumped every time the shell reports a finished command; the first bump
+    /// is the startup prompt.
+    generation: u64,
+    last_exit: i32,
+    run: Option<ActiveRun>,
+    closed: bool,
+}
+
+struct ActiveRun {
+    /// Scrollback line the command's output starts on.
+    first_line: u64,
+    channel: Channel<RunOutput>,
+    binary: BinaryFilter,
+}
+
+/// Where a running command's output is in the scrollback, sent as it grows.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunOutput {
+    first_line: u64,
+    line_count: u64,
+}
+
+impl Session {
+    fn lock_state(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Resolves once the shell reports a command finishing after
+    /// `generation`, or the session closes. Returns the new generation.
+    async fn wait_past(&self, generation: u64) -> u64 {
+        loop {
+            let changed = self.changed.notified();
+            {
+                let state = self.lock_state();
+                if state.generation > generation || state.closed {
+                    return state.generation;
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Decodes output and records it in the scrollback, returning the text to
+    /// show. A command's binary output is captured instead, and `None`
+    /// returned.
+    fn capture(&self, raw: &[u8]) -> Option<String> {
+        if let Some(run) = &mut self.lock_state().run {
+            if !run.binary.filter(raw) {
+                return None;
+            }
+        }
+        let text = self.encoding.lock().unwrap().output.decode(raw);
+        let end = {
+            let mut scrollback = self.scrollback.lock().unwrap();
+            scrollback.feed(text.as_bytes());
+            scrollback.end()
+        };
+        let state = self.lock_state();
+        if let Some(ActiveRun {
+            first_line,
+            channel,
+            ..
+        }) = &state.run
+        {
+            let _ = channel.send(RunOutput {
+                first_line: *first_line,
+                line_count: end.saturating_sub(*first_line),
+            });
+        }
+        Some(text)
+    }
+
+    /// Writes to the shell in the session's input encoding.
+    fn write_input(&self, data: &str) -> CommandResponse<()> {
+        let bytes = encode_input(self.encoding.lock().unwrap().input, data);
+        self.pty.lock().unwrap().write(&bytes)
+    }
+
+    fn apply(&self, marker: Marker) {
+        let mut state = self.lock_state();
+        match marker {
+            Marker::Cwd(cwd) => state.cwd = cwd,
+            Marker::CommandFinished(code) => {
+                state.last_exit = code;
+                state.generation += 1;
+                self.changed.notify_waiters();
+            }
+        }
+    }
+
+    /// Kills whatever the shell is running in the foreground. If the shell
+    /// itself is in the foreground (a builtin or a loop) it is interrupted
+    /// instead, so the session survives.
+    fn kill_foreground(&self) {
+        let pty = self.pty.lock().unwrap();
+        let Some(group) = pty.foreground_process_group() else {
+            return;
+        };
+        if Some(group) == pty.process_id() {
+            signal_group(group, Stage::Interrupt);
+        } else {
+            signal_group(group, Stage::Kill);
+        }
+    }
+
+    /// Runs a command the user didn't type, such as a project's startup
+    /// commands, and waits for the prompt after it. The caller holds
+    /// `run_lock`.
+    async fn run_hidden(&self, command: &str) -> CommandResponse<()> {
+        let generation = self.wait_past(0).await;
+        self.write_input(&format!("{command}\n"))?;
+        match tokio::time::timeout(HIDDEN_TIMEOUT, self.wait_past(generation)).await {
+            Ok(current) if current > generation => Ok(()),
+            Ok(_) => Err(CommandError::Cancelled),
+            Err(_) => {
+                self.kill_foreground();
+                let _ = tokio::time::timeout(KILL_GRACE, self.wait_past(generation)).await;
+                Err(CommandError::Timeout {
+                    after_ms: HIDDEN_TIMEOUT.as_millis() as u64,
+                })
+            }
+        }
+    }
+
+    /// Looks for the project file of the shell's directory. Entering a
+    /// trusted project applies it and leaving one undoes its env. The caller
+    /// holds `run_lock`.
+    async fn sync_project(&self, trust: &TrustStore) -> Option<ProjectStatus> {
+        self.integration.as_ref()?;
+        self.wait_past(0).await;
+        let cwd = self.lock_state().cwd.clone();
+        let file = project::find(Path::new(&cwd)).and_then(|path| ProjectFile::read(&path).ok());
+        let unchanged = match (&self.project.lock().unwrap().current, &file) {
+            (Some(current), Some(file)) => current.path == file.path && current.hash == file.hash,
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            self.unapply_project().await;
+            self.project.lock().unwrap().current =
+                file.map(|file| ProjectStatus::new(&file, trust.trust(&file)));
+            self.apply_project().await;
+        }
+        self.take_project_status()
+    }
+
+    /// Exports the current project's env and runs its startup commands, if
+    /// it is trusted and that hasn't been done yet.
+    async fn apply_project(&self) {
+        let config = match &*self.project.lock().unwrap() {
+            ProjectState {
+                current: Some(status),
+                applied: false,
+                ..
+            } if status.applies() => status.config.clone(),
+            _ => return,
+        };
+        let shell = shell_name(&self.program);
+        let first_line = {
+            let mut scrollback = self.scrollback.lock().unwrap();
+            scrollback.finish_line();
+            scrollback.end()
+        };
+        let mut commands = Vec::new();
+        if !config.env.is_empty() {
+            let exports: Vec<_> = config
+                .env
+                .iter()
+                .map(|(key, value)| export_command(shell, key, value))
+                .collect();
+            commands.push(exports.join("; "));
+        }
+        commands.extend(config.startup.iter().cloned());
+        let mut failure = None;
+        for command in &commands {
+            if let Err(err) = self.run_hidden(command).await {
+                failure = Some(err);
+                break;
+            }
+        }
+
+        let line_count = self.scrollback.lock().unwrap().end() - first_line;
+        let mut project = self.project.lock().unwrap();
+        project.applied = true;
+        project.exported = config.env.into_keys().collect();
+        if let Some(status) = &mut project.current {
+            status.output = Some(RunOutput {
+                first_line,
+                line_count,
+            });
+            status.error = failure.map(|err| ConfigError {
+                path: status.path.clone(),
+                line: None,
+                column: None,
+                message: err.to_string(),
+            });
+        }
+    }
+
+    /// Puts back the env the current project changed.
+    async fn unapply_project(&self) {
+        let exported = {
+            let mut project = self.project.lock().unwrap();
+            project.applied = false;
+            std::mem::take(&mut project.exported)
+        };
+        if exported.is_empty() {
+            return;
+        }
+        let shell = shell_name(&self.program);
+        let restore: Vec<_> = exported
+            .iter()
+            .map(|key| {
+                let previous = self.env.get(key).cloned();
+                match previous.or_else(|| std::env::var(key).ok()) {
+                    Some(value) => export_command(shell, key, &value),
+                    None => unset_command(shell, key),
+                }
+            })
+            .collect();
+        let _ = self.run_hidden(&restore.join("; ")).await;
+    }
+
+    /// The current project. Startup output is handed over, so it's only
+    /// reported once.
+    fn take_project_status(&self) -> Option<ProjectStatus> {
+        let mut project = self.project.lock().unwrap();
+        let status = project.current.clone();
+        if let Some(current) = &mut project.current {
+            current.output = None;
+        }
+        status
+    }
+
+    /// The aliases in effect: the config's, then the profile's, then those
+    /// of a project that has been applied, then the session's own.
+    pub fn aliases(&self, config: &Config) -> Vec<Alias> {
+        let mut table = BTreeMap::new();
+        let mut add = |aliases: &BTreeMap<String, String>, source: AliasSource| {
+            for (name, value) in aliases {
+                table.insert(name.clone(), (value.clone(), source));
+            }
+        };
+        add(&config.aliases, AliasSource::Config);
+        add(&self.aliases, AliasSource::Profile);
+        if let ProjectState {
+            current: Some(status),
+            applied: true,
+            ..
+        } = &*self.project.lock().unwrap()
+        {
+            add(&status.config.aliases, AliasSource::Project);
+        }
+        for (name, value) in self.alias_overrides.lock().unwrap().iter() {
+            match value {
+                Some(value) => {
+                    table.insert(name.clone(), (value.clone(), AliasSource::Session));
+                }
+                None => {
+                    table.remove(name);
+                }
+            }
+        }
+        table
+            .into_iter()
+            .map(|(name, (value, source))| Alias {
+                name,
+                value,
+                source,
+            })
+            .collect()
+    }
+
+    /// Defines an alias for this session, or with `None` removes one.
+    pub fn set_alias(&self, name: String, value: Option<String>) {
+        self.alias_overrides.lock().unwrap().insert(name, value);
+    }
+
+    fn mark_closed(&self) {
+        self.lock_state().closed = true;
+        self.changed.notify_waiters();
+    }
+
+    fn info(&self, id: u32) -> SessionInfo {
+        let encoding = self.encoding.lock().unwrap();
+        SessionInfo {
+            id,
+            cwd: self.lock_state().cwd.clone(),
+            env: self.env.clone(),
+            shell: ShellConfig {
+                program: Some(self.program.clone()),
+                args: self.args.clone(),
+            },
+            encoding: encoding.output.encoding().name(),
+            input_encoding: encoding.input.name(),
+            integrated: self.integration.is_some(),
+            profile: self.profile.clone(),
+            title: self.title.clone(),
+            theme: self.theme.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SessionManager {
+    next_id: AtomicU32,
+    sessions: Mutex<HashMap<u32, Arc<Session>>>,
+}
+
+impl SessionManager {
+    pub fn get(&self, id: u32) -> CommandResponse<Arc<Session>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(CommandError::UnknownId { id: id.into() })
+    }
+
+    fn remove(&self, id: u32) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().remove(&id)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: u32,
+    cwd: String,
+    env: HashMap<String, String>,
+    shell: ShellConfig,
+    /// "auto" or the name of the encoding output is decoded from.
+    encoding: &'static str,
+    input_encoding: &'static str,
+    /// Whether commands can be run with `run_in_session`. Without shell
+    /// integration (a REPL, ssh) the session is only a screen to type into.
+    integrated: bool,
+    profile: Option<String>,
+    title: Option<String>,
+    theme: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRunResult {
+    /// The command's output, stdout and stderr alike, as a range of the
+    /// session's scrollback.
+    first_line: u64,
+    line_count: u64,
+    /// Set when the command printed binary data, which is left out of the
+    /// scrollback.
+    binary: Option<BinarySummary>,
+    exit_code: i32,
+    /// The shell's working directory once the command finished.
+    cwd: String,
+    duration_ms: u64,
+    started_at: u64,
+    /// The project the shell is now in. Its `output` is set when entering it
+    /// ran startup commands.
+    project: Option<ProjectStatus>,
+}
+
+#[derive(Clone, Serialize)]
+struct SessionScreen {
+    id: u32,
+    screen: Option<ScreenSnapshot>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionExit {
+    id: u32,
+    exit_code: Option<u32>,
+}
+
+fn spawn_reader(app: AppHandle, id: u32, session: Arc<Session>, mut reader: Box<dyn Read + Send>) {
+    thread::spawn(move || {
+        let mut scanner = OscScanner::default();
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            if let Some(channel) = &session.raw_output {
+                let _ = channel.send(Response::new(buf[..n].to_vec()));
+            }
+            let mut forwarded = Vec::with_capacity(n);
+            scanner.feed(&buf[..n], |piece| match piece {
+                Piece::Text(raw) => {
+                    if let Some(text) = session.capture(raw) {
+                        forwarded.extend_from_slice(text.as_bytes());
+                    }
+                }
+                Piece::Osc(payload) => {
+                    let marker = session
+                        .integration
+                        .as_ref()
+                        .and_then(|integration| Marker::parse(payload, &integration.nonce));
+                    match marker {
+                        Some(marker) => session.apply(marker),
+                        None => encode_osc(payload, &mut forwarded),
+                    }
+                }
+            });
+            // A raw frontend draws the screen, and answers the program's
+            // queries, itself.
+            if !forwarded.is_empty() && session.raw_output.is_none() {
+                let mut terminal = session.terminal.lock().unwrap();
+                let was_alternate = terminal.alternate();
+                let replies = terminal.feed(&forwarded);
+                let drawn = terminal.alternate() || session.integration.is_none();
+                let screen = drawn.then(|| terminal.snapshot());
+                drop(terminal);
+                if !replies.is_empty() {
+                    let _ = session.pty.lock().unwrap().write(replies.as_bytes());
+                }
+                // Full-screen programs, and sessions whose prompts can't be
+                // told apart from output, are drawn from the emulated screen;
+                // `None` tells the frontend to go back to the block view.
+                if was_alternate || screen.is_some() {
+                    let _ = app.emit("session-screen", SessionScreen { id, screen });
+                }
+            }
+        }
+        session.mark_closed();
+        app.state::<SessionManager>().remove(id);
+        let exit_code = session.pty.lock().unwrap().wait();
+        let _ = app.emit("session-exit", SessionExit { id, exit_code });
+    });
+}
+
+/// Everything a session can be started with; profiles fill in some of it.
+/// `env` is added to the one from the config file.
+#[derive(Default)]
+pub struct SessionOptions {
+    pub cwd: Option<String>,
+    pub env: HashMap<String, String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    pub scrollback_lines: Option<usize>,
+    pub encoding: Option<String>,
+    pub input_encoding: Option<String>,
+    pub shell: ShellConfig,
+    pub profile: Option<String>,
+    pub title: Option<String>,
+    pub theme: Option<String>,
+    pub aliases: BTreeMap<String, String>,
+    /// Sends the PTY's output as is. The shell then runs without integration,
+    /// as in any other terminal.
+    pub raw_output: Option<Channel<Response>>,
+}
+
+pub fn open_session(
+    app: AppHandle,
+    manager: &SessionManager,
+    options: SessionOptions,
+) -> CommandResponse<SessionInfo> {
+    let SessionOptions {
+        cwd,
+        env,
+        cols,
+        rows,
+        scrollback_lines,
+        encoding,
+        input_encoding,
+        shell,
+        profile,
+        title,
+        theme,
+        aliases,
+        raw_output,
+    } = options;
+    let encoding = SessionEncoding::parse(encoding.as_deref(), input_encoding.as_deref())?;
+    let shell = shell.resolve()?;
+    let program = shell.program.expect("resolved shell has a program");
+    let id = manager.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let integration = match raw_output {
+        Some(_) => None,
+        None => ShellIntegration::prepare(&program).map_err(CommandError::io)?,
+    };
+    validate_env(&env)?;
+    let env = {
+        let mut configured = app.state::<ConfigStore>().config().env;
+        configured.extend(env);
+        configured
+    };
+
+    let mut launch_env = env.clone();
+    let mut args = Vec::new();
+    if let Some(integration) = &integration {
+        args.extend(integration.args.iter().cloned());
+        launch_env.extend(integration.env.iter().cloned());
+    }
+    args.extend(shell.args.iter().cloned());
+
+    let cols = cols.unwrap_or(DEFAULT_COLS);
+    let rows = rows.unwrap_or(DEFAULT_ROWS);
+    let (pty, reader) = Pty::spawn(&program, &args, cwd.as_deref(), &launch_env, cols, rows)?;
+    let session = Arc::new(Session {
+        program,
+        args: shell.args,
+        pty: Mutex::new(pty),
+        env,
+        state: Mutex::new(SessionState {
+            cwd: cwd.or_else(home_dir).unwrap_or_default(),
+            ..Default::default()
+        }),
+        changed: Notify::new(),
+        run_lock: tokio::sync::Mutex::new(()),
+        integration,
+        terminal: Mutex::new(Terminal::new(cols, rows)),
+        scrollback: Mutex::new(Scrollback::new(
+            scrollback_lines.unwrap_or(scrollback::DEFAULT_LIMIT),
+        )),
+        encoding: Mutex::new(encoding),
+        profile,
+        title,
+        theme,
+        project: Mutex::new(ProjectState::default()),
+        aliases,
+        alias_overrides: Mutex::new(BTreeMap::new()),
+        raw_output,
+    });
+
+    manager.sessions.lock().unwrap().insert(id, session.clone());
+    spawn_reader(app, id, session.clone(), reader);
+    Ok(session.info(id))
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_session(
+    app: AppHandle,
+    manager: State<'_, SessionManager>,
+    cwd: Option<String>,
+    env: Option<HashMap<String, String>>,
+    cols: Option<u16>,
+    rows: Option<u16>,
+    scrollback_lines: Option<usize>,
+    encoding: Option<String>,
+    input_encoding: Option<String>,
+    shell: Option<ShellConfig>,
+) -> CommandResponse<SessionInfo> {
+    let shell = shell.unwrap_or_else(|| app.state::<ConfigStore>().config().shell);
+    open_session(
+        app,
+        &manager,
+        SessionOptions {
+            cwd,
+            env: env.unwrap_or_default(),
+            cols,
+            rows,
+            scrollback_lines,
+            encoding,
+            input_encoding,
+            shell,
+            ..Default::default()
+        },
+    )
+}
+
+/// How long to wait for the prompt after killing a timed-out command.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// How long a command the user didn't type may run.
+const HIDDEN_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_in_session(
+    manager: State<'_, SessionManager>,
+    defaults: State<'_, Mutex<CommandDefaults>>,
+    binaries: State<'_, BinaryOutputs>,
+    trust: State<'_, TrustStore>,
+    id: u32,
+    command: String,
+    on_output: Channel<RunOutput>,
+    timeout_ms: Option<u64>,
+    background: Option<bool>,
+) -> CommandResponse<SessionRunResult> {
+    let timeout = defaults
+        .lock()
+        .unwrap()
+        .timeout(timeout_ms, background.unwrap_or(false));
+    let session = manager.get(id)?;
+    if session.integration.is_none() {
+        return Err(CommandError::NoShellIntegration {
+            program: session.program.clone(),
+        });
+    }
+    let _running = session.run_lock.lock().await;
+
+    // Wait for the startup prompt so it isn't mistaken for this command's end.
+    let generation = session.wait_past(0).await;
+    let started_at = unix_millis(SystemTime::now());
+    let started = Instant::now();
+    let first_line = {
+        let mut scrollback = session.scrollback.lock().unwrap();
+        scrollback.finish_line();
+        scrollback.end()
+    };
+    session.lock_state().run = Some(ActiveRun {
+        first_line,
+        channel: on_output,
+        binary: BinaryFilter::default(),
+    });
+
+    if let Err(err) = session.write_input(&format!("{command}\n")) {
+        session.lock_state().run = None;
+        return Err(err);
+    }
+
+    let finished = match timeout {
+        Some(limit) => {
+            match tokio::time::timeout(limit, session.wait_past(generation)).await {
+                Ok(current) => current > generation,
+                Err(_) => {
+                    session.kill_foreground();
+                    // Let the prompt for the killed command arrive so the
+                    // next run doesn't take it for its own.
+                    let _ = tokio::time::timeout(KILL_GRACE, session.wait_past(generation)).await;
+                    session.lock_state().run = None;
+                    return Err(CommandError::Timeout {
+                        after_ms: limit.as_millis() as u64,
+                    });
+                }
+            }
+        }
+        None => session.wait_past(generation).await > generation,
+    };
+    let line_count = session.scrollback.lock().unwrap().end() - first_line;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let (run, exit_code, cwd) = {
+        let mut state = session.lock_state();
+        (state.run.take(), state.last_exit, state.cwd.clone())
+    };
+    if !finished {
+        return Err(CommandError::Cancelled);
+    }
+    let project = session.sync_project(&trust).await;
+
+    Ok(SessionRunResult {
+        first_line,
+        line_count,
+        binary: run
+            .and_then(|run| run.binary.finish())
+            .map(|capture| binaries.keep(capture)),
+        exit_code,
+        cwd,
+        duration_ms,
+        started_at,
+        project,
+    })
+}
+
+/// The project the shell's directory is in, applying it if it's trusted.
+/// Commands run in the session check this themselves.
+#[tauri::command]
+pub async fn check_project(
+    manager: State<'_, SessionManager>,
+    trust: State<'_, TrustStore>,
+    id: u32,
+) -> CommandResponse<Option<ProjectStatus>> {
+    let session = manager.get(id)?;
+    let _running = session.run_lock.lock().await;
+    Ok(session.sync_project(&trust).await)
+}
+
+/// Records the user's answer about a project file. The answer covers the
+/// contents they were shown, identified by `hash`, so a file edited since
+/// isn't approved with it. The project is applied, or undone, if it's the
+/// session's current one, and its status returned; other answers leave the
+/// session alone.
+#[tauri::command]
+pub async fn trust_project(
+    manager: State<'_, SessionManager>,
+    trust: State<'_, TrustStore>,
+    id: u32,
+    path: String,
+    hash: String,
+    trusted: bool,
+) -> CommandResponse<Option<ProjectStatus>> {
+    let session = manager.get(id)?;
+    trust.decide(&path, &hash, trusted)?;
+    let _running = session.run_lock.lock().await;
+    let mut current = false;
+    if let Some(status) = &mut session.project.lock().unwrap().current {
+        if status.path == path && status.hash == hash {
+            status.trust = if trusted {
+                Trust::Trusted
+            } else {
+                Trust::Denied
+            };
+            current = true;
+        }
+    }
+    if !current {
+        return Ok(None);
+    }
+    if trusted {
+        session.apply_project().await;
+    } else {
+        session.unapply_project().await;
+    }
+    Ok(session.take_project_status())
+}
+
+#[tauri::command]
+pub async fn write_session(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    data: String,
+) -> CommandResponse<()> {
+    manager.get(id)?.write_input(&data)
+}
+
+/// Changes how the session's output is decoded ("auto" or an encoding label,
+/// `None` meaning "auto") and what its input is encoded in (by default the
+/// output's encoding, or UTF-8 for "auto").
+#[tauri::command]
+pub fn set_session_encoding(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    encoding: Option<String>,
+    input_encoding: Option<String>,
+) -> CommandResponse<SessionInfo> {
+    let session = manager.get(id)?;
+    *session.encoding.lock().unwrap() =
+        SessionEncoding::parse(encoding.as_deref(), input_encoding.as_deref())?;
+    Ok(session.info(id))
+}
+
+/// Updates the PTY window size (TIOCSWINSZ) and tells the foreground job.
+#[tauri::command]
+pub async fn resize_session(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    cols: u16,
+    rows: u16,
+) -> CommandResponse<()> {
+    let session = manager.get(id)?;
+    let pty = session.pty.lock().unwrap();
+    pty.resize(cols, rows)?;
+    session.terminal.lock().unwrap().resize(cols, rows);
+    pty.notify_resize();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_session(manager: State<'_, SessionManager>, id: u32) -> CommandResponse<SessionInfo> {
+    Ok(manager.get(id)?.info(id))
+}
+
+/// The session's emulated screen, as last drawn by the program.
+#[tauri::command]
+pub fn get_screen(manager: State<'_, SessionManager>, id: u32) -> CommandResponse<ScreenSnapshot> {
+    Ok(manager.get(id)?.terminal.lock().unwrap().snapshot())
+}
+
+/// Returns up to `count` lines of the session's output history from line
+/// `start`, for rendering only what is on screen.
+#[tauri::command]
+pub fn get_scrollback(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    start: u64,
+    count: usize,
+) -> CommandResponse<ScrollbackRange> {
+    Ok(manager
+        .get(id)?
+        .scrollback
+        .lock()
+        .unwrap()
+        .range(start, count))
+}
+
+const DEFAULT_SEARCH_RESULTS: usize = 1000;
+
+/// Line numbers of the newest scrollback lines containing `query`, ignoring
+/// case. Spilled lines are searched too.
+#[tauri::command]
+pub fn search_scrollback(
+    manager: State<'_, SessionManager>,
+    id: u32,
+    query: String,
+    max_results: Option<usize>,
+) -> CommandResponse<Vec<u64>> {
+    let session = manager.get(id)?;
+    let scrollback = session.scrollback.lock().unwrap();
+    Ok(scrollback.search(&query, max_results.unwrap_or(DEFAULT_SEARCH_RESULTS)))
+}
+
+#[tauri::command]
+pub fn close_session(manager: State<'_, SessionManager>, id: u32) -> CommandResponse<()> {
+    let session = manager
+        .remove(id)
+        .ok_or(CommandError::UnknownId { id: id.into() })?;
+    session.pty.lock().unwrap().kill();
+    session.mark_closed();
+    Ok(())
+}
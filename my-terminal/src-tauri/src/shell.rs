@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CommandError, CommandResponse};
+use crate::pty::home_dir;
+
+/// Which shell to run. Without a program the user's login shell is used.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// A name looked up on `PATH` ("bash", "zsh", "fish", "nu") or a path.
+    pub program: Option<String>,
+    /// Passed after any arguments shell integration adds.
+    pub args: Vec<String>,
+}
+
+impl ShellConfig {
+    /// Checks the shell exists and returns the config with `program` set to
+    /// its full path.
+    pub fn resolve(&self) -> CommandResponse<ShellConfig> {
+        let program = match &self.program {
+            Some(program) => find_program(program)?,
+            None => login_shell(),
+        };
+        Ok(ShellConfig {
+            program: Some(program),
+            args: self.args.clone(),
+        })
+    }
+}
+
+/// The shell's name without directory or extension, e.g. "bash".
+pub fn shell_name(program: &str) -> &str {
+    Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+}
+
+/// The flag that makes `program` run a command string and exit.
+pub fn command_flag(program: &str) -> &'static str {
+    match shell_name(program) {
+        "cmd" => "/C",
+        "powershell" | "pwsh" => "-Command",
+        _ => "-c",
+    }
+}
+
+/// The user's login shell: `$SHELL`, else the passwd entry, else `/bin/sh`.
+#[cfg(unix)]
+pub fn login_shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|shell| is_executable(Path::new(shell)))
+        .or_else(passwd_shell)
+        .unwrap_or_else(|| "/bin/sh".into())
+}
+
+#[cfg(not(unix))]
+pub fn login_shell() -> String {
+    std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".into())
+}
+
+#[cfg(unix)]
+fn passwd_shell() -> Option<String> {
+    use std::ffi::CStr;
+
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let status = unsafe {
+        libc::getpwuid_r(
+            libc::getuid(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if status != 0 || found.is_null() || entry.pw_shell.is_null() {
+        return None;
+    }
+    let shell = unsafe { CStr::from_ptr(entry.pw_shell) }.to_str().ok()?;
+    Some(shell.to_string()).filter(|shell| is_executable(Path::new(shell)))
+}
+
+/// Finds `program` on `PATH`, unless it's a path itself (`~/` allowed), and
+/// checks it can be run.
+pub fn find_program(program: &str) -> CommandResponse<String> {
+    let not_found = || CommandError::NotFound {
+        program: program.into(),
+    };
+    let path = match program.strip_prefix("~/") {
+        Some(rest) => Path::new(&home_dir().ok_or_else(not_found)?).join(rest),
+        None => PathBuf::from(program),
+    };
+    if path.components().count() > 1 {
+        if !path.is_absolute() || !path.is_file() {
+            return Err(not_found());
+        }
+        if !is_executable(&path) {
+            return Err(CommandError::PermissionDenied {
+                path: program.into(),
+            });
+        }
+        return Ok(path.to_string_lossy().into_owned());
+    }
+
+    let path_var = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path_var)
+        .flat_map(|dir| {
+            executable_names(program)
+                .into_iter()
+                .map(move |name| dir.join(name))
+        })
+        .find(|candidate| is_executable(candidate))
+        .map(|found| found.to_string_lossy().into_owned())
+        .ok_or_else(not_found)
+}
+
+#[cfg(unix)]
+fn executable_names(program: &str) -> Vec<String> {
+    vec![program.to_string()]
+}
+
+#[cfg(not(unix))]
+fn executable_names(program: &str) -> Vec<String> {
+    if Path::new(program).extension().is_some() {
+        return vec![program.to_string()];
+    }
+    ["exe", "cmd", "bat"]
+        .iter()
+        .map(|ext| format!("{program}.{ext}"))
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
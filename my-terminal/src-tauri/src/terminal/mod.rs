@@ -0,0 +1,70 @@
+//! Terminal emulation: a VT parser driving a cell grid, so the backend knows
+//! what full-screen programs have drawn.
+
+mod parser;
+mod screen;
+mod style;
+
+pub use screen::ScreenSnapshot;
+pub use style::{push_span, Span};
+
+use crate::command::take_utf8;
+use parser::Parser;
+use screen::Screen;
+use style::SpanWriter;
+
+pub struct Terminal {
+    parser: Parser,
+    screen: Screen,
+    pending: Vec<u8>,
+}
+
+impl Terminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Terminal {
+            parser: Parser::default(),
+            screen: Screen::new(cols.into(), rows.into()),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feeds raw program output. Returns any replies to status queries,
+    /// which belong on the program's input.
+    pub fn feed(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let text = take_utf8(&mut self.pending);
+        self.parser.advance(&mut self.screen, &text);
+        self.screen.take_responses()
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.screen.resize(cols.into(), rows.into());
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        self.screen.snapshot()
+    }
+
+    /// Whether a full-screen program is using the alternate screen.
+    pub fn alternate(&self) -> bool {
+        self.screen.alternate()
+    }
+}
+
+/// Converts captured command output into styled spans, keeping colors but
+/// not cursor movement.
+#[derive(Default)]
+pub struct SpanStream {
+    parser: Parser,
+    writer: SpanWriter,
+    pending: Vec<u8>,
+}
+
+impl SpanStream {
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Span> {
+        self.pending.extend_from_slice(data);
+        let text = take_utf8(&mut self.pending);
+        self.parser.advance(&mut self.writer, &text);
+        self.writer.take_spans()
+    }
+}
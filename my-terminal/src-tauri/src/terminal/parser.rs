@@ -0,0 +1,393 @@
+//! A VT500-series parser following Paul Williams' state diagram
+//! (https://vt100.net/emu/dec_ansi_parser). It works on decoded characters
+//! rather than bytes, so C1 controls arrive as U+0080..U+009F.
+
+const MAX_PARAMS: usize = 32;
+const MAX_INTERMEDIATES: usize = 2;
+const MAX_OSC_LEN: usize = 4096;
+
+/// Receives the actions the parser recognises.
+pub trait Perform {
+    /// A printable character.
+    fn print(&mut self, c: char);
+    /// A C0 or C1 control function.
+    fn execute(&mut self, c: char);
+    /// A control sequence. Each parameter holds its value followed by any
+    /// `:`-separated sub-parameters; omitted values are 0. Private markers
+    /// (`?`, `>`, ...) are reported as the first intermediate.
+    fn csi_dispatch(&mut self, params: &[Vec<u16>], intermediates: &[char], action: char);
+    fn esc_dispatch(&mut self, intermediates: &[char], action: char);
+    /// An operating system command, without the `ESC ]` and terminator.
+    fn osc_dispatch(&mut self, data: &str);
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    OscString,
+    /// DCS, SOS, PM and APC strings, which we skip.
+    StringIgnore,
+}
+
+#[derive(Default)]
+pub struct Parser {
+    state: State,
+    intermediates: Vec<char>,
+    params: Vec<Vec<u16>>,
+    param: Vec<u16>,
+    value: u16,
+    has_params: bool,
+    osc: String,
+}
+
+impl Parser {
+    pub fn advance(&mut self, performer: &mut impl Perform, text: &str) {
+        for c in text.chars() {
+            self.step(performer, c);
+        }
+    }
+
+    fn step(&mut self, performer: &mut impl Perform, c: char) {
+        // Transitions that apply in every state.
+        match c {
+            '\u{18}' | '\u{1a}' => {
+                performer.execute(c);
+                self.state = State::Ground;
+                return;
+            }
+            '\u{1b}' => {
+                if self.state == State::OscString {
+                    self.dispatch_osc(performer);
+                }
+                self.enter(State::Escape);
+                return;
+            }
+            '\u{9b}' => return self.enter(State::CsiEntry),
+            '\u{9d}' => return self.enter(State::OscString),
+            '\u{90}' | '\u{98}' | '\u{9e}' | '\u{9f}' => return self.enter(State::StringIgnore),
+            '\u{9c}' => {
+                if self.state == State::OscString {
+                    self.dispatch_osc(performer);
+                }
+                self.state = State::Ground;
+                return;
+            }
+            '\u{80}'..='\u{9f}' => {
+                performer.execute(c);
+                self.state = State::Ground;
+                return;
+            }
+            _ => {}
+        }
+
+        let is_c0 = c < ' ';
+        match self.state {
+            State::Ground if is_c0 => performer.execute(c),
+            State::Ground if c == '\u{7f}' => {}
+            State::Ground => performer.print(c),
+
+            State::Escape
+            | State::EscapeIntermediate
+            | State::CsiEntry
+            | State::CsiParam
+            | State::CsiIntermediate
+            | State::CsiIgnore
+                if is_c0 =>
+            {
+                performer.execute(c)
+            }
+
+            State::Escape => match c {
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::EscapeIntermediate;
+                }
+                '[' => self.enter(State::CsiEntry),
+                ']' => self.enter(State::OscString),
+                'P' | 'X' | '^' | '_' => self.enter(State::StringIgnore),
+                '0'..='~' => {
+                    performer.esc_dispatch(&self.intermediates, c);
+                    self.state = State::Ground;
+                }
+                _ => {}
+            },
+            State::EscapeIntermediate => match c {
+                ' '..='/' => self.collect(c),
+                '0'..='~' => {
+                    performer.esc_dispatch(&self.intermediates, c);
+                    self.state = State::Ground;
+                }
+                _ => {}
+            },
+
+            State::CsiEntry => match c {
+                '0'..='9' | ':' | ';' => {
+                    self.param_char(c);
+                    self.state = State::CsiParam;
+                }
+                '<'..='?' => {
+                    self.collect(c);
+                    self.state = State::CsiParam;
+                }
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::CsiIntermediate;
+                }
+                '@'..='~' => self.dispatch_csi(performer, c),
+                _ => {}
+            },
+            State::CsiParam => match c {
+                '0'..='9' | ':' | ';' => self.param_char(c),
+                '<'..='?' => self.state = State::CsiIgnore,
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::CsiIntermediate;
+                }
+                '@'..='~' => self.dispatch_csi(performer, c),
+                _ => {}
+            },
+            State::CsiIntermediate => match c {
+                ' '..='/' => self.collect(c),
+                '0'..='?' => self.state = State::CsiIgnore,
+                '@'..='~' => self.dispatch_csi(performer, c),
+                _ => {}
+            },
+            State::CsiIgnore => {
+                if ('@'..='~').contains(&c) {
+                    self.state = State::Ground;
+                }
+            }
+
+            State::OscString => match c {
+                '\u{07}' => {
+                    self.dispatch_osc(performer);
+                    self.state = State::Ground;
+                }
+                _ if is_c0 => {}
+                _ if self.osc.len() < MAX_OSC_LEN => self.osc.push(c),
+                _ => {}
+            },
+            State::StringIgnore => {}
+        }
+    }
+
+    fn enter(&mut self, state: State) {
+        self.state = state;
+        self.intermediates.clear();
+        self.params.clear();
+        self.param.clear();
+        self.value = 0;
+        self.has_params = false;
+        self.osc.clear();
+    }
+
+    fn collect(&mut self, c: char) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(c);
+        }
+    }
+
+    fn param_char(&mut self, c: char) {
+        self.has_params = true;
+        match c {
+            ':' => {
+                self.param.push(self.value);
+                self.value = 0;
+            }
+            ';' => {
+                self.param.push(self.value);
+                self.value = 0;
+                if self.params.len() < MAX_PARAMS {
+                    self.params.push(std::mem::take(&mut self.param));
+                } else {
+                    self.param.clear();
+                }
+            }
+            digit => {
+                let digit = digit as u16 - '0' as u16;
+                self.value = self.value.saturating_mul(10).saturating_add(digit);
+            }
+        }
+    }
+
+    fn dispatch_csi(&mut self, performer: &mut impl Perform, action: char) {
+        if self.has_params && self.params.len() < MAX_PARAMS {
+            self.param.push(self.value);
+            self.params.push(std::mem::take(&mut self.param));
+        }
+        performer.csi_dispatch(&self.params, &self.intermediates, action);
+        self.state = State::Ground;
+    }
+
+    fn dispatch_osc(&mut self, performer: &mut impl Perform) {
+        performer.osc_dispatch(&self.osc);
+        self.osc.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Action {
+        Print(char),
+        Execute(char),
+        Csi(Vec<Vec<u16>>, Vec<char>, char),
+        Esc(Vec<char>, char),
+        Osc(String),
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<Action>);
+
+    impl Perform for Recorder {
+        fn print(&mut self, c: char) {
+            self.0.push(Action::Print(c));
+        }
+        fn execute(&mut self, c: char) {
+            self.0.push(Action::Execute(c));
+        }
+        fn csi_dispatch(&mut self, params: &[Vec<u16>], intermediates: &[char], action: char) {
+            self.0
+                .push(Action::Csi(params.to_vec(), intermediates.to_vec(), action));
+        }
+        fn esc_dispatch(&mut self, intermediates: &[char], action: char) {
+            self.0.push(Action::Esc(intermediates.to_vec(), action));
+        }
+        fn osc_dispatch(&mut self, data: &str) {
+            self.0.push(Action::Osc(data.to_string()));
+        }
+    }
+
+    fn parse(text: &str) -> Vec<Action> {
+        let mut recorder = Recorder::default();
+        Parser::default().advance(&mut recorder, text);
+        recorder.0
+    }
+
+    fn csi(params: &[&[u16]], intermediates: &[char], action: char) -> Action {
+        let params = params.iter().map(|param| param.to_vec()).collect();
+        Action::Csi(params, intermediates.to_vec(), action)
+    }
+
+    #[test]
+    fn prints_and_executes() {
+        assert_eq!(
+            parse("a\tb\r\n\x7f"),
+            [
+                Action::Print('a'),
+                Action::Execute('\t'),
+                Action::Print('b'),
+                Action::Execute('\r'),
+                Action::Execute('\n'),
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_csi_parameters() {
+        assert_eq!(parse("\x1b[H"), [csi(&[], &[], 'H')]);
+        assert_eq!(parse("\x1b[3;5H"), [csi(&[&[3], &[5]], &[], 'H')]);
+        // Omitted parameters are 0.
+        assert_eq!(parse("\x1b[;5H"), [csi(&[&[0], &[5]], &[], 'H')]);
+        assert_eq!(parse("\x1b[2;r"), [csi(&[&[2], &[0]], &[], 'r')]);
+        assert_eq!(
+            parse("\x1b[38:2:1:2:3m"),
+            [csi(&[&[38, 2, 1, 2, 3]], &[], 'm')]
+        );
+        assert_eq!(parse("\x1b[99999999A"), [csi(&[&[u16::MAX]], &[], 'A')]);
+    }
+
+    #[test]
+    fn caps_the_number_of_parameters() {
+        let many = format!("\x1b[{}m", ["1"; 40].join(";"));
+        let actions = parse(&many);
+        let [Action::Csi(params, _, 'm')] = &actions[..] else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(params.len(), MAX_PARAMS);
+    }
+
+    #[test]
+    fn reports_private_markers_and_intermediates() {
+        assert_eq!(parse("\x1b[?25h"), [csi(&[&[25]], &['?'], 'h')]);
+        assert_eq!(parse("\x1b[>c"), [csi(&[], &['>'], 'c')]);
+        assert_eq!(parse("\x1b[!p"), [csi(&[], &['!'], 'p')]);
+        assert_eq!(parse("\x1b(0"), [Action::Esc(vec!['('], '0')]);
+        assert_eq!(parse("\x1b#8"), [Action::Esc(vec!['#'], '8')]);
+        assert_eq!(parse("\x1b7"), [Action::Esc(vec![], '7')]);
+    }
+
+    #[test]
+    fn ignores_malformed_csi() {
+        // A private marker after parameters makes the sequence invalid.
+        assert_eq!(parse("\x1b[1?hx"), [Action::Print('x')]);
+        assert_eq!(parse("\x1b[ 1hx"), [Action::Print('x')]);
+    }
+
+    #[test]
+    fn runs_controls_inside_sequences() {
+        assert_eq!(
+            parse("\x1b[1\n2H"),
+            [Action::Execute('\n'), csi(&[&[12]], &[], 'H')]
+        );
+    }
+
+    #[test]
+    fn can_and_sub_abort_sequences() {
+        assert_eq!(
+            parse("\x1b[1\x18A"),
+            [Action::Execute('\x18'), Action::Print('A')]
+        );
+        assert_eq!(
+            parse("\x1b]0;t\x1aB"),
+            [Action::Execute('\x1a'), Action::Print('B')]
+        );
+    }
+
+    #[test]
+    fn escape_restarts_a_sequence() {
+        assert_eq!(parse("\x1b[12\x1b[3A"), [csi(&[&[3]], &[], 'A')]);
+    }
+
+    #[test]
+    fn accepts_c1_controls() {
+        assert_eq!(parse("\u{9b}2J"), [csi(&[&[2]], &[], 'J')]);
+        assert_eq!(parse("\u{9d}2;t\u{9c}"), [Action::Osc("2;t".into())]);
+        assert_eq!(parse("\u{84}"), [Action::Execute('\u{84}')]);
+    }
+
+    #[test]
+    fn dispatches_osc_strings() {
+        assert_eq!(parse("\x1b]0;title\x07"), [Action::Osc("0;title".into())]);
+        assert_eq!(
+            parse("\x1b]7;file:///tmp\x1b\\"),
+            [
+                Action::Osc("7;file:///tmp".into()),
+                Action::Esc(vec![], '\\')
+            ]
+        );
+        let long = format!("\x1b]{}\x07", "x".repeat(MAX_OSC_LEN + 10));
+        let [Action::Osc(data)] = &parse(&long)[..] else {
+            panic!();
+        };
+        assert_eq!(data.len(), MAX_OSC_LEN);
+    }
+
+    #[test]
+    fn skips_dcs_and_other_strings() {
+        assert_eq!(
+            parse("\x1bPq#0;2;0;0;0\x1b\\x"),
+            [Action::Esc(vec![], '\\'), Action::Print('x')]
+        );
+        assert_eq!(parse("\x1b_apc\u{9c}y"), [Action::Print('y')]);
+    }
+}
@@ -0,0 +1,1221 @@
+use serde::Serialize;
+use unicode_width::UnicodeWidthChar;
+
+use super::parser::Perform;
+use super::style::{push_span, Attrs, Span};
+
+const TAB_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    /// 2 for the first half of a wide character, 0 for the second half.
+    pub width: u8,
+    pub attrs: Attrs,
+}
+
+#[derive(Clone, Debug)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// The line continues on the next row because it was auto-wrapped.
+    pub wrapped: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Charset {
+    #[default]
+    Ascii,
+    /// DEC Special Graphics, used for line drawing.
+    DecSpecial,
+}
+
+// DEC Special Graphics for '`'..='~'.
+const DEC_SPECIAL: &str = "◆▒␉␌␍␊°±␤␋┘┐┌└┼⎺⎻─⎼⎽├┤┴┬│≤≥π≠£·";
+
+#[derive(Clone, Copy, Default)]
+struct Cursor {
+    row: usize,
+    col: usize,
+    attrs: Attrs,
+    /// Set after printing in the last column; the next character wraps.
+    pending_wrap: bool,
+    charsets: [Charset; 2],
+    shift: usize,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Modes {
+    pub autowrap: bool,
+    pub origin: bool,
+    pub insert: bool,
+    pub cursor_visible: bool,
+    pub app_cursor_keys: bool,
+    pub bracketed_paste: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Modes {
+            autowrap: true,
+            origin: false,
+            insert: false,
+            cursor_visible: true,
+            app_cursor_keys: false,
+            bracketed_paste: false,
+        }
+    }
+}
+
+/// Screen contents that aren't being shown: the primary screen while a
+/// full-screen program has the alternate one, or the other way round.
+struct Buffer {
+    lines: Vec<Row>,
+    saved: Option<Cursor>,
+}
+
+/// What the frontend needs to draw the screen.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenSnapshot {
+    cols: usize,
+    rows: usize,
+    lines: Vec<Vec<Span>>,
+    cursor_row: usize,
+    cursor_col: usize,
+    modes: Modes,
+    /// Whether a full-screen program has switched to the alternate screen.
+    alternate: bool,
+    title: String,
+}
+
+/// The visible cell grid of a VT100/xterm-style terminal.
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    lines: Vec<Row>,
+    cursor: Cursor,
+    saved: Option<Cursor>,
+    inactive: Buffer,
+    alternate: bool,
+    /// Scroll region, inclusive.
+    top: usize,
+    bottom: usize,
+    tabs: Vec<bool>,
+    modes: Modes,
+    last_printed: Option<char>,
+    title: String,
+    /// Replies to status queries, to be written back to the application.
+    responses: String,
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        let mut screen = Screen {
+            cols,
+            rows,
+            lines: Vec::new(),
+            cursor: Cursor::default(),
+            saved: None,
+            inactive: Buffer {
+                lines: Vec::new(),
+                saved: None,
+            },
+            alternate: false,
+            top: 0,
+            bottom: rows - 1,
+            tabs: default_tabs(cols),
+            modes: Modes::default(),
+            last_printed: None,
+            title: String::new(),
+            responses: String::new(),
+        };
+        screen.lines = (0..rows).map(|_| screen.blank_row()).collect();
+        screen.inactive.lines = screen.lines.clone();
+        screen
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        let blank = self.blank();
+
+        let dropped = resize_lines(&mut self.lines, cols, rows, blank, self.cursor.row);
+        self.cursor.row -= dropped;
+        if let Some(saved) = &mut self.saved {
+            saved.row = saved.row.saturating_sub(dropped);
+        }
+        let keep = self.inactive.saved.map_or(0, |saved| saved.row);
+        let dropped = resize_lines(&mut self.inactive.lines, cols, rows, blank, keep);
+        if let Some(saved) = &mut self.inactive.saved {
+            saved.row = saved.row.saturating_sub(dropped);
+        }
+
+        if cols != self.cols {
+            self.tabs.resize(cols, false);
+            for col in self.cols..cols {
+                self.tabs[col] = col % TAB_WIDTH == 0;
+            }
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn alternate(&self) -> bool {
+        self.alternate
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let lines = self.lines.iter().map(line_spans).collect();
+        ScreenSnapshot {
+            cols: self.cols,
+            rows: self.rows,
+            lines,
+            cursor_row: self.cursor.row,
+            cursor_col: self.cursor.col,
+            modes: self.modes,
+            alternate: self.alternate,
+            title: self.title.clone(),
+        }
+    }
+
+    pub fn take_responses(&mut self) -> String {
+        std::mem::take(&mut self.responses)
+    }
+
+    fn blank(&self) -> Cell {
+        // Erased cells keep the current background (xterm's BCE).
+        Cell {
+            c: ' ',
+            width: 1,
+            attrs: Attrs {
+                bg: self.cursor.attrs.bg,
+                ..Attrs::default()
+            },
+        }
+    }
+
+    fn blank_row(&self) -> Row {
+        Row {
+            cells: vec![self.blank(); self.cols],
+            wrapped: false,
+        }
+    }
+
+    fn goto(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Moves to a 0-based position that is relative to the scroll region in
+    /// origin mode.
+    fn goto_origin(&mut self, row: usize, col: usize) {
+        let row = if self.modes.origin {
+            (self.top + row).min(self.bottom)
+        } else {
+            row
+        };
+        self.goto(row, col);
+    }
+
+    fn cursor_up(&mut self, n: usize) {
+        let limit = if self.cursor.row >= self.top {
+            self.top
+        } else {
+            0
+        };
+        let row = self.cursor.row.saturating_sub(n).max(limit);
+        self.goto(row, self.cursor.col);
+    }
+
+    fn cursor_down(&mut self, n: usize) {
+        let limit = if self.cursor.row <= self.bottom {
+            self.bottom
+        } else {
+            self.rows - 1
+        };
+        let row = (self.cursor.row + n).min(limit);
+        self.goto(row, self.cursor.col);
+    }
+
+    /// Line feed: moves down, scrolling the region at its bottom margin.
+    fn index(&mut self) {
+        if self.cursor.row == self.bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let n = n.min(self.bottom - self.top + 1);
+        self.lines[self.top..=self.bottom].rotate_left(n);
+        for row in self.bottom + 1 - n..=self.bottom {
+            self.lines[row] = self.blank_row();
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.bottom - self.top + 1);
+        self.lines[self.top..=self.bottom].rotate_right(n);
+        for row in self.top..self.top + n {
+            self.lines[row] = self.blank_row();
+        }
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        let row = self.cursor.row;
+        if row < self.top || row > self.bottom {
+            return;
+        }
+        let n = n.min(self.bottom - row + 1);
+        self.lines[row..=self.bottom].rotate_right(n);
+        for line in row..row + n {
+            self.lines[line] = self.blank_row();
+        }
+        self.goto(row, 0);
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        let row = self.cursor.row;
+        if row < self.top || row > self.bottom {
+            return;
+        }
+        let n = n.min(self.bottom - row + 1);
+        self.lines[row..=self.bottom].rotate_left(n);
+        for line in self.bottom + 1 - n..=self.bottom {
+            self.lines[line] = self.blank_row();
+        }
+        self.goto(row, 0);
+    }
+
+    fn insert_cells(&mut self, n: usize) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let n = n.min(self.cols - col);
+        let blank = self.blank();
+        let cells = &mut self.lines[row].cells[col..];
+        cells.rotate_right(n);
+        cells[..n].fill(blank);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn delete_cells(&mut self, n: usize) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let n = n.min(self.cols - col);
+        let blank = self.blank();
+        let cells = &mut self.lines[row].cells[col..];
+        cells.rotate_left(n);
+        let len = cells.len();
+        cells[len - n..].fill(blank);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn erase_cells(&mut self, row: usize, start: usize, end: usize) {
+        let blank = self.blank();
+        let end = end.min(self.cols);
+        if start < end {
+            self.lines[row].cells[start..end].fill(blank);
+        }
+    }
+
+    fn erase_rows(&mut self, start: usize, end: usize) {
+        for row in start..end {
+            self.lines[row] = self.blank_row();
+        }
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match mode {
+            0 => {
+                self.erase_cells(row, col, self.cols);
+                self.lines[row].wrapped = false;
+                self.erase_rows(row + 1, self.rows);
+            }
+            1 => {
+                self.erase_rows(0, row);
+                self.erase_cells(row, 0, col + 1);
+            }
+            2 => self.erase_rows(0, self.rows),
+            _ => {}
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match mode {
+            0 => {
+                self.erase_cells(row, col, self.cols);
+                self.lines[row].wrapped = false;
+            }
+            1 => self.erase_cells(row, 0, col + 1),
+            2 => {
+                self.erase_cells(row, 0, self.cols);
+                self.lines[row].wrapped = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn tab_forward(&mut self, n: usize) {
+        for _ in 0..n {
+            let next = (self.cursor.col + 1..self.cols).find(|&col| self.tabs[col]);
+            self.cursor.col = next.unwrap_or(self.cols - 1);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn tab_backward(&mut self, n: usize) {
+        for _ in 0..n {
+            let prev = (0..self.cursor.col).rev().find(|&col| self.tabs[col]);
+            self.cursor.col = prev.unwrap_or(0);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn clear_tabs(&mut self, mode: u16) {
+        match mode {
+            0 => self.tabs[self.cursor.col] = false,
+            3 => self.tabs.fill(false),
+            _ => {}
+        }
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let top = top.saturating_sub(1);
+        let bottom = if bottom == 0 {
+            self.rows
+        } else {
+            bottom.min(self.rows)
+        } - 1;
+        if top < bottom {
+            self.top = top;
+            self.bottom = bottom;
+            self.goto_origin(0, 0);
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = Some(self.cursor);
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved.unwrap_or_default();
+        self.cursor = saved;
+        self.cursor.row = saved.row.min(self.rows - 1);
+        self.cursor.col = saved.col.min(self.cols - 1);
+    }
+
+    /// Switches between the primary and alternate screens. Each keeps its
+    /// own contents and saved cursor.
+    fn swap_buffers(&mut self) {
+        std::mem::swap(&mut self.lines, &mut self.inactive.lines);
+        std::mem::swap(&mut self.saved, &mut self.inactive.saved);
+        self.alternate = !self.alternate;
+    }
+
+    fn enter_alternate(&mut self, clear: bool) {
+        if !self.alternate {
+            self.swap_buffers();
+            if clear {
+                self.erase_rows(0, self.rows);
+            }
+        }
+    }
+
+    fn leave_alternate(&mut self, clear: bool) {
+        if self.alternate {
+            if clear {
+                self.erase_rows(0, self.rows);
+            }
+            self.swap_buffers();
+        }
+    }
+
+    /// Splits any wide character that `col` is half of, so overwriting one
+    /// half doesn't leave the other dangling.
+    fn split_wide(&mut self, row: usize, col: usize) {
+        let blank = self.blank();
+        let cells = &mut self.lines[row].cells;
+        match cells[col].width {
+            0 if col > 0 => cells[col - 1] = blank,
+            2 if col + 1 < cells.len() => cells[col + 1] = blank,
+            _ => {}
+        }
+    }
+
+    fn wrap(&mut self) {
+        self.lines[self.cursor.row].wrapped = true;
+        self.cursor.col = 0;
+        self.index();
+    }
+
+    fn translate(&self, c: char) -> char {
+        if self.cursor.charsets[self.cursor.shift] != Charset::DecSpecial {
+            return c;
+        }
+        match c {
+            '_' => ' ',
+            '`'..='~' => DEC_SPECIAL
+                .chars()
+                .nth(c as usize - '`' as usize)
+                .unwrap_or(c),
+            _ => c,
+        }
+    }
+
+    fn mode(&mut self, private: bool, params: &[Vec<u16>], enable: bool) {
+        for param in params {
+            match (private, param[0]) {
+                (false, 4) => self.modes.insert = enable,
+                (true, 1) => self.modes.app_cursor_keys = enable,
+                (true, 6) => {
+                    self.modes.origin = enable;
+                    self.goto_origin(0, 0);
+                }
+                (true, 7) => self.modes.autowrap = enable,
+                (true, 25) => self.modes.cursor_visible = enable,
+                (true, 47) if enable => self.enter_alternate(false),
+                (true, 47) => self.leave_alternate(false),
+                (true, 1047) if enable => self.enter_alternate(false),
+                (true, 1047) => self.leave_alternate(true),
+                (true, 1048) if enable => self.save_cursor(),
+                (true, 1048) => self.restore_cursor(),
+                (true, 1049) if enable => {
+                    self.save_cursor();
+                    self.enter_alternate(true);
+                }
+                (true, 1049) => {
+                    self.leave_alternate(false);
+                    self.restore_cursor();
+                }
+                (true, 2004) => self.modes.bracketed_paste = enable,
+                _ => {}
+            }
+        }
+    }
+
+    fn report(&mut self, query: u16) {
+        match query {
+            5 => self.responses.push_str("\x1b[0n"),
+            6 => {
+                let row = if self.modes.origin {
+                    self.cursor.row.saturating_sub(self.top)
+                } else {
+                    self.cursor.row
+                };
+                let reply = format!("\x1b[{};{}R", row + 1, self.cursor.col + 1);
+                self.responses.push_str(&reply);
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        let title = std::mem::take(&mut self.title);
+        *self = Screen::new(self.cols, self.rows);
+        self.title = title;
+    }
+
+    /// DECSTR: resets modes and attributes but leaves the screen alone.
+    fn soft_reset(&mut self) {
+        self.modes = Modes::default();
+        self.cursor.attrs = Attrs::default();
+        self.cursor.charsets = Default::default();
+        self.cursor.shift = 0;
+        self.cursor.pending_wrap = false;
+        self.saved = None;
+        self.top = 0;
+        self.bottom = self.rows - 1;
+    }
+
+    /// DECALN: fills the screen with `E` for alignment tests.
+    fn align(&mut self) {
+        for line in &mut self.lines {
+            line.cells.fill(Cell {
+                c: 'E',
+                width: 1,
+                attrs: Attrs::default(),
+            });
+            line.wrapped = false;
+        }
+        self.top = 0;
+        self.bottom = self.rows - 1;
+        self.goto(0, 0);
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        let c = self.translate(c);
+        // Combining marks have no cell of their own; they are dropped.
+        let width = match c.width() {
+            Some(width @ 1..=2) => width,
+            _ => return,
+        };
+        if self.cursor.pending_wrap && self.modes.autowrap {
+            self.wrap();
+        }
+        if width == 2 && self.cursor.col + 1 >= self.cols {
+            if self.cols < 2 {
+                return;
+            }
+            if self.modes.autowrap {
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                self.erase_cells(row, col, col + 1);
+                self.wrap();
+            } else {
+                self.cursor.col = self.cols - 2;
+            }
+        }
+        if self.modes.insert {
+            self.insert_cells(width);
+        }
+
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        self.split_wide(row, col);
+        if width == 2 {
+            self.split_wide(row, col + 1);
+        }
+        let attrs = self.cursor.attrs;
+        let cells = &mut self.lines[row].cells;
+        cells[col] = Cell {
+            c,
+            width: width as u8,
+            attrs,
+        };
+        if width == 2 {
+            cells[col + 1] = Cell {
+                c: ' ',
+                width: 0,
+                attrs,
+            };
+        }
+        self.last_printed = Some(c);
+
+        let next = col + width;
+        if next < self.cols {
+            self.cursor.col = next;
+        } else {
+            self.cursor.col = self.cols - 1;
+            self.cursor.pending_wrap = self.modes.autowrap;
+        }
+    }
+
+    fn execute(&mut self, c: char) {
+        match c {
+            '\u{08}' => {
+                let col = self.cursor.col.saturating_sub(1);
+                self.goto(self.cursor.row, col);
+            }
+            '\t' => self.tab_forward(1),
+            '\n' | '\u{0b}' | '\u{0c}' | '\u{84}' => self.index(),
+            '\r' => self.goto(self.cursor.row, 0),
+            '\u{0e}' => self.cursor.shift = 1,
+            '\u{0f}' => self.cursor.shift = 0,
+            '\u{85}' => {
+                self.index();
+                self.cursor.col = 0;
+            }
+            '\u{88}' => self.tabs[self.cursor.col] = true,
+            '\u{8d}' => self.reverse_index(),
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &[Vec<u16>], intermediates: &[char], action: char) {
+        // Missing and zero parameters both take the default.
+        let arg = |i: usize, default: usize| match params.get(i).map(|p| p[0]) {
+            None | Some(0) => default,
+            Some(n) => n as usize,
+        };
+        let raw = |i: usize| params.get(i).map_or(0, |p| p[0]);
+        let (row, col) = (self.cursor.row, self.cursor.col);
+
+        match (intermediates.first(), action) {
+            (None, '@') => self.insert_cells(arg(0, 1)),
+            (None, 'A') => self.cursor_up(arg(0, 1)),
+            (None, 'B' | 'e') => self.cursor_down(arg(0, 1)),
+            (None, 'C' | 'a') => self.goto(row, col + arg(0, 1)),
+            (None, 'D') => self.goto(row, col.saturating_sub(arg(0, 1))),
+            (None, 'E') => {
+                self.cursor_down(arg(0, 1));
+                self.cursor.col = 0;
+            }
+            (None, 'F') => {
+                self.cursor_up(arg(0, 1));
+                self.cursor.col = 0;
+            }
+            (None, 'G' | '`') => self.goto(row, arg(0, 1) - 1),
+            (None, 'H' | 'f') => self.goto_origin(arg(0, 1) - 1, arg(1, 1) - 1),
+            (None, 'I') => self.tab_forward(arg(0, 1)),
+            (None | Some('?'), 'J') => self.erase_display(raw(0)),
+            (None | Some('?'), 'K') => self.erase_line(raw(0)),
+            (None, 'L') => self.insert_lines(arg(0, 1)),
+            (None, 'M') => self.delete_lines(arg(0, 1)),
+            (None, 'P') => self.delete_cells(arg(0, 1)),
+            (None, 'S') => self.scroll_up(arg(0, 1)),
+            // With more parameters this is a mouse tracking request.
+            (None, 'T') if params.len() <= 1 => self.scroll_down(arg(0, 1)),
+            (None, 'X') => self.erase_cells(row, col, col + arg(0, 1)),
+            (None, 'Z') => self.tab_backward(arg(0, 1)),
+            (None, 'b') => {
+                if let Some(c) = self.last_printed {
+                    for _ in 0..arg(0, 1).min(self.cols * self.rows) {
+                        self.print(c);
+                    }
+                }
+            }
+            (None, 'c') if raw(0) == 0 => self.responses.push_str("\x1b[?62;22c"),
+            (Some('>'), 'c') if raw(0) == 0 => self.responses.push_str("\x1b[>1;10;0c"),
+            (None, 'd') => {
+                let col = self.cursor.col;
+                self.goto_origin(arg(0, 1) - 1, col);
+            }
+            (None, 'g') => self.clear_tabs(raw(0)),
+            (None, 'h') => self.mode(false, params, true),
+            (None, 'l') => self.mode(false, params, false),
+            (Some('?'), 'h') => self.mode(true, params, true),
+            (Some('?'), 'l') => self.mode(true, params, false),
+            (None, 'm') => self.cursor.attrs.apply_sgr(params),
+            (None, 'n') => self.report(raw(0)),
+            (None, 'r') => self.set_scroll_region(raw(0) as usize, raw(1) as usize),
+            (None, 's') => self.save_cursor(),
+            (None, 'u') => self.restore_cursor(),
+            (Some('!'), 'p') => self.soft_reset(),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[char], action: char) {
+        match (intermediates.first(), action) {
+            (None, '7') => self.save_cursor(),
+            (None, '8') => self.restore_cursor(),
+            (None, 'D') => self.index(),
+            (None, 'E') => {
+                self.index();
+                self.cursor.col = 0;
+            }
+            (None, 'H') => self.tabs[self.cursor.col] = true,
+            (None, 'M') => self.reverse_index(),
+            (None, 'c') => self.reset(),
+            (Some('#'), '8') => self.align(),
+            (Some(designator @ ('(' | ')')), set) => {
+                let slot = if *designator == '(' { 0 } else { 1 };
+                self.cursor.charsets[slot] = match set {
+                    '0' => Charset::DecSpecial,
+                    _ => Charset::Ascii,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, data: &str) {
+        let (command, rest) = data.split_once(';').unwrap_or((data, ""));
+        if let "0" | "2" = command {
+            self.title = rest.to_string();
+        }
+    }
+}
+
+/// Fits a buffer to a new size. When rows are removed, lines are dropped from
+/// the top as needed to keep row `keep` on screen; returns how many.
+fn resize_lines(lines: &mut Vec<Row>, cols: usize, rows: usize, blank: Cell, keep: usize) -> usize {
+    for line in lines.iter_mut() {
+        line.cells.resize(cols, blank);
+        if line.cells[cols - 1].width == 2 {
+            line.cells[cols - 1] = blank;
+        }
+    }
+    let dropped = (keep + 1).saturating_sub(rows).min(lines.len());
+    lines.drain(..dropped);
+    lines.truncate(rows);
+    lines.resize(
+        rows,
+        Row {
+            cells: vec![blank; cols],
+            wrapped: false,
+        },
+    );
+    dropped
+}
+
+/// Converts a row to spans, leaving off trailing unstyled blanks.
+fn line_spans(line: &Row) -> Vec<Span> {
+    let blank = |cell: &Cell| cell.c == ' ' && cell.attrs == Attrs::default();
+    let end = line
+        .cells
+        .iter()
+        .rposition(|cell| !blank(cell))
+        .map_or(0, |i| i + 1);
+    let mut spans = Vec::new();
+    for cell in line.cells[..end].iter().filter(|cell| cell.width > 0) {
+        push_span(&mut spans, cell.c.encode_utf8(&mut [0; 4]), cell.attrs);
+    }
+    spans
+}
+
+fn default_tabs(cols: usize) -> Vec<bool> {
+    (0..cols)
+        .map(|col| col > 0 && col % TAB_WIDTH == 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::Parser;
+    use super::super::style::Color;
+    use super::*;
+
+    fn run(screen: &mut Screen, input: &str) {
+        Parser::default().advance(screen, input);
+    }
+
+    fn screen(cols: usize, rows: usize, input: &str) -> Screen {
+        let mut screen = Screen::new(cols, rows);
+        run(&mut screen, input);
+        screen
+    }
+
+    /// The rows as text, without trailing blanks.
+    fn text(screen: &Screen) -> Vec<String> {
+        screen
+            .lines
+            .iter()
+            .map(|line| {
+                let text: String = line
+                    .cells
+                    .iter()
+                    .filter(|cell| cell.width > 0)
+                    .map(|cell| cell.c)
+                    .collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    fn cursor(screen: &Screen) -> (usize, usize) {
+        (screen.cursor.row, screen.cursor.col)
+    }
+
+    const LINES: &str = "A\r\nB\r\nC\r\nD\r\nE";
+
+    #[test]
+    fn cup_moves_and_clamps() {
+        let mut s = screen(10, 5, "\x1b[3;5H");
+        assert_eq!(cursor(&s), (2, 4));
+        run(&mut s, "\x1b[H");
+        assert_eq!(cursor(&s), (0, 0));
+        run(&mut s, "\x1b[99;99H");
+        assert_eq!(cursor(&s), (4, 9));
+        run(&mut s, "\x1b[0;0f");
+        assert_eq!(cursor(&s), (0, 0));
+    }
+
+    #[test]
+    fn cuu_and_cud_stop_at_the_margins() {
+        // Region rows 2-4; DECSTBM homes the cursor.
+        let mut s = screen(10, 5, "\x1b[3;3H\x1b[2;4r");
+        assert_eq!(cursor(&s), (0, 0));
+
+        run(&mut s, "\x1b[3;3H\x1b[10A");
+        assert_eq!(cursor(&s), (1, 2));
+        run(&mut s, "\x1b[10B");
+        assert_eq!(cursor(&s), (3, 2));
+
+        // Outside the region only the screen edges stop the cursor.
+        run(&mut s, "\x1b[1;1H\x1b[A");
+        assert_eq!(cursor(&s), (0, 0));
+        run(&mut s, "\x1b[5;1H\x1b[5B");
+        assert_eq!(cursor(&s), (4, 0));
+        // Above or below the region, moving towards it stops at its margin.
+        run(&mut s, "\x1b[10A");
+        assert_eq!(cursor(&s), (1, 0));
+        run(&mut s, "\x1b[1;1H\x1b[10B");
+        assert_eq!(cursor(&s), (3, 0));
+    }
+
+    #[test]
+    fn cuf_cub_cha_and_vpa() {
+        let mut s = screen(10, 5, "\x1b[2;2H\x1b[3C");
+        assert_eq!(cursor(&s), (1, 4));
+        run(&mut s, "\x1b[20C");
+        assert_eq!(cursor(&s), (1, 9));
+        run(&mut s, "\x1b[2D");
+        assert_eq!(cursor(&s), (1, 7));
+        run(&mut s, "\x1b[20D");
+        assert_eq!(cursor(&s), (1, 0));
+        run(&mut s, "\x1b[6G");
+        assert_eq!(cursor(&s), (1, 5));
+        run(&mut s, "\x1b[4d");
+        assert_eq!(cursor(&s), (3, 5));
+        run(&mut s, "\x1b[2E");
+        assert_eq!(cursor(&s), (4, 0));
+        run(&mut s, "\x1b[3;3H\x1b[F");
+        assert_eq!(cursor(&s), (1, 0));
+    }
+
+    #[test]
+    fn origin_mode_is_relative_to_the_region() {
+        let mut s = screen(10, 5, "\x1b[2;4r\x1b[?6h");
+        assert_eq!(cursor(&s), (1, 0));
+        run(&mut s, "\x1b[2;3H");
+        assert_eq!(cursor(&s), (2, 2));
+        run(&mut s, "\x1b[99;1H");
+        assert_eq!(cursor(&s), (3, 0));
+        run(&mut s, "\x1b[10A");
+        assert_eq!(cursor(&s), (1, 0));
+        run(&mut s, "\x1b[2d");
+        assert_eq!(cursor(&s), (2, 0));
+
+        // DSR reports the position within the region.
+        run(&mut s, "\x1b[2;3H\x1b[6n");
+        assert_eq!(s.take_responses(), "\x1b[2;3R");
+
+        run(&mut s, "\x1b[?6l");
+        assert_eq!(cursor(&s), (0, 0));
+        run(&mut s, "\x1b[2;3H\x1b[6n");
+        assert_eq!(s.take_responses(), "\x1b[2;3R");
+        assert_eq!(cursor(&s), (1, 2));
+    }
+
+    #[test]
+    fn decstbm_rejects_empty_regions() {
+        let mut s = screen(10, 5, "\x1b[2;4r");
+        run(&mut s, "\x1b[3;3r");
+        assert_eq!((s.top, s.bottom), (1, 3));
+        run(&mut s, "\x1b[4;2r");
+        assert_eq!((s.top, s.bottom), (1, 3));
+        run(&mut s, "\x1b[r");
+        assert_eq!((s.top, s.bottom), (0, 4));
+        run(&mut s, "\x1b[2;99r");
+        assert_eq!((s.top, s.bottom), (1, 4));
+    }
+
+    #[test]
+    fn ind_and_ri_scroll_at_the_margins() {
+        let mut s = screen(5, 5, LINES);
+        run(&mut s, "\x1b[2;4r\x1b[4;1H\x1bD");
+        assert_eq!(text(&s), ["A", "C", "D", "", "E"]);
+        assert_eq!(cursor(&s), (3, 0));
+
+        run(&mut s, "\x1b[2;1H\x1bM");
+        assert_eq!(text(&s), ["A", "", "C", "D", "E"]);
+        assert_eq!(cursor(&s), (1, 0));
+
+        // Away from the margins they only move the cursor.
+        run(&mut s, "\x1b[3;2H\x1bD");
+        assert_eq!(cursor(&s), (3, 1));
+        run(&mut s, "\x1bM\x1bM");
+        assert_eq!(cursor(&s), (1, 1));
+        assert_eq!(text(&s), ["A", "", "C", "D", "E"]);
+
+        // Below the region the last row doesn't scroll; above it the first.
+        run(&mut s, "\x1b[5;1H\x1bD\n");
+        assert_eq!(cursor(&s), (4, 0));
+        run(&mut s, "\x1b[1;1H\x1bM");
+        assert_eq!(cursor(&s), (0, 0));
+        assert_eq!(text(&s), ["A", "", "C", "D", "E"]);
+    }
+
+    #[test]
+    fn line_feed_scrolls_the_screen() {
+        let mut s = screen(5, 3, "a\r\nb\r\nc\r\nd");
+        assert_eq!(text(&s), ["b", "c", "d"]);
+        assert_eq!(cursor(&s), (2, 1));
+        // NEL also returns to the first column.
+        run(&mut s, "\x1bEe");
+        assert_eq!(text(&s), ["c", "d", "e"]);
+    }
+
+    #[test]
+    fn il_and_dl_stay_within_the_region() {
+        let mut s = screen(5, 5, LINES);
+        run(&mut s, "\x1b[2;4H\x1b[2L");
+        assert_eq!(text(&s), ["A", "", "", "B", "C"]);
+        assert_eq!(cursor(&s), (1, 0));
+
+        let mut s = screen(5, 5, LINES);
+        run(&mut s, "\x1b[2;1H\x1b[2M");
+        assert_eq!(text(&s), ["A", "D", "E", "", ""]);
+
+        let mut s = screen(5, 5, LINES);
+        run(&mut s, "\x1b[2;4r\x1b[2;1H\x1b[L");
+        assert_eq!(text(&s), ["A", "", "B", "C", "E"]);
+        run(&mut s, "\x1b[3;1H\x1b[9M");
+        assert_eq!(text(&s), ["A", "", "", "", "E"]);
+
+        // Outside the region they do nothing.
+        let mut s = screen(5, 5, LINES);
+        run(&mut s, "\x1b[2;4r\x1b[5;1H\x1b[L\x1b[1;1H\x1b[M");
+        assert_eq!(text(&s), ["A", "B", "C", "D", "E"]);
+    }
+
+    #[test]
+    fn ich_and_dch_shift_the_rest_of_the_line() {
+        let s = screen(8, 1, "abcdefgh\x1b[1;2H\x1b[2@");
+        assert_eq!(text(&s), ["a  bcdef"]);
+        assert_eq!(cursor(&s), (0, 1));
+
+        let s = screen(8, 1, "abcdefgh\x1b[1;2H\x1b[2P");
+        assert_eq!(text(&s), ["adefgh"]);
+
+        let s = screen(8, 1, "abcdefgh\x1b[1;7H\x1b[9P");
+        assert_eq!(text(&s), ["abcdef"]);
+        let s = screen(8, 1, "abcdefgh\x1b[1;7H\x1b[9@");
+        assert_eq!(text(&s), ["abcdef"]);
+
+        // ECH erases without shifting.
+        let s = screen(8, 1, "abcdefgh\x1b[1;2H\x1b[2X");
+        assert_eq!(text(&s), ["a  defgh"]);
+    }
+
+    #[test]
+    fn insert_mode_shifts_printed_text() {
+        let s = screen(8, 1, "abcdef\x1b[1;2H\x1b[4hXY\x1b[4lZ");
+        assert_eq!(text(&s), ["aXYZcdef"]);
+    }
+
+    fn filled() -> Screen {
+        screen(5, 3, "aaaaabbbbbccccc\x1b[2;3H")
+    }
+
+    #[test]
+    fn ed_modes() {
+        let mut s = filled();
+        run(&mut s, "\x1b[J");
+        assert_eq!(text(&s), ["aaaaa", "bb", ""]);
+        let mut s = filled();
+        run(&mut s, "\x1b[1J");
+        assert_eq!(text(&s), ["", "   bb", "ccccc"]);
+        let mut s = filled();
+        run(&mut s, "\x1b[2J");
+        assert_eq!(text(&s), ["", "", ""]);
+        assert_eq!(cursor(&s), (1, 2));
+    }
+
+    #[test]
+    fn el_modes() {
+        let mut s = filled();
+        run(&mut s, "\x1b[K");
+        assert_eq!(text(&s), ["aaaaa", "bb", "ccccc"]);
+        let mut s = filled();
+        run(&mut s, "\x1b[1K");
+        assert_eq!(text(&s), ["aaaaa", "   bb", "ccccc"]);
+        let mut s = filled();
+        run(&mut s, "\x1b[2K");
+        assert_eq!(text(&s), ["aaaaa", "", "ccccc"]);
+        assert_eq!(cursor(&s), (1, 2));
+    }
+
+    #[test]
+    fn erasing_keeps_the_background() {
+        let s = screen(5, 1, "abcde\x1b[44m\x1b[1;3H\x1b[K");
+        let cells = &s.lines[0].cells;
+        assert_eq!(cells[1].attrs.bg, Color::Default);
+        assert_eq!(cells[2].attrs.bg, Color::Indexed(4));
+        assert_eq!(cells[4].attrs.bg, Color::Indexed(4));
+    }
+
+    #[test]
+    fn tab_stops() {
+        let mut s = screen(20, 1, "\t");
+        assert_eq!(cursor(&s), (0, 8));
+        run(&mut s, "\t");
+        assert_eq!(cursor(&s), (0, 16));
+        run(&mut s, "\t");
+        assert_eq!(cursor(&s), (0, 19));
+
+        // HTS sets one, TBC 0 clears the one under the cursor.
+        run(&mut s, "\x1b[1;4H\x1bH\r\t");
+        assert_eq!(cursor(&s), (0, 3));
+        run(&mut s, "\x1b[1;9H\x1b[g\r\t\t");
+        assert_eq!(cursor(&s), (0, 16));
+
+        // CHT and CBT move over several stops.
+        run(&mut s, "\r\x1b[2I");
+        assert_eq!(cursor(&s), (0, 16));
+        run(&mut s, "\x1b[1;18H\x1b[Z");
+        assert_eq!(cursor(&s), (0, 16));
+        run(&mut s, "\x1b[2Z");
+        assert_eq!(cursor(&s), (0, 0));
+        run(&mut s, "\x1b[Z");
+        assert_eq!(cursor(&s), (0, 0));
+
+        // TBC 3 clears them all.
+        run(&mut s, "\x1b[3g\r\t");
+        assert_eq!(cursor(&s), (0, 19));
+        run(&mut s, "\x1b[Z");
+        assert_eq!(cursor(&s), (0, 0));
+    }
+
+    #[test]
+    fn autowrap_waits_for_the_next_character() {
+        let mut s = screen(5, 3, "abcde");
+        assert_eq!(text(&s), ["abcde", "", ""]);
+        assert_eq!(cursor(&s), (0, 4));
+        assert!(s.cursor.pending_wrap);
+
+        run(&mut s, "f");
+        assert_eq!(text(&s), ["abcde", "f", ""]);
+        assert_eq!(cursor(&s), (1, 1));
+        assert!(s.lines[0].wrapped);
+
+        // Cursor movement cancels the pending wrap.
+        let s = screen(5, 3, "abcde\rX");
+        assert_eq!(text(&s), ["Xbcde", "", ""]);
+        let s = screen(5, 3, "abcde\x08X");
+        assert_eq!(text(&s), ["abcXe", "", ""]);
+        let s = screen(5, 3, "abcde\x1b[1;5HX");
+        assert_eq!(text(&s), ["abcdX", "", ""]);
+        assert_eq!(cursor(&s), (0, 4));
+
+        // Wrapping at the bottom scrolls.
+        let s = screen(3, 2, "abcdefg");
+        assert_eq!(text(&s), ["def", "g"]);
+    }
+
+    #[test]
+    fn without_autowrap_the_last_column_is_overwritten() {
+        let s = screen(5, 2, "\x1b[?7labcdefg");
+        assert_eq!(text(&s), ["abcdg", ""]);
+        assert_eq!(cursor(&s), (0, 4));
+    }
+
+    #[test]
+    fn wide_characters_wrap_at_the_right_margin() {
+        let s = screen(5, 2, "abcd中");
+        assert_eq!(text(&s), ["abcd", "中"]);
+        assert_eq!(cursor(&s), (1, 2));
+        assert!(s.lines[0].wrapped);
+
+        let s = screen(6, 2, "abcd中");
+        assert_eq!(text(&s), ["abcd中", ""]);
+        assert_eq!(cursor(&s), (0, 5));
+        assert!(s.cursor.pending_wrap);
+
+        // Without autowrap the character goes in the last two columns.
+        let s = screen(5, 2, "\x1b[?7labcd中");
+        assert_eq!(text(&s), ["abc中", ""]);
+        assert_eq!(
+            (s.lines[0].cells[3].width, s.lines[0].cells[4].width),
+            (2, 0)
+        );
+    }
+
+    #[test]
+    fn overwriting_half_a_wide_character_clears_the_other_half() {
+        let s = screen(6, 1, "中文\x1b[1;2Hx");
+        assert_eq!(text(&s), [" x文"]);
+        let s = screen(6, 1, "中文\x1b[1;3Hx");
+        assert_eq!(text(&s), ["中x"]);
+        assert_eq!(s.lines[0].cells[3].c, ' ');
+        assert_eq!(s.lines[0].cells[3].width, 1);
+    }
+
+    #[test]
+    fn decaln_fills_the_screen() {
+        let s = screen(3, 2, "\x1b[2;2H\x1b[1;2r\x1b#8");
+        assert_eq!(text(&s), ["EEE", "EEE"]);
+        assert_eq!(cursor(&s), (0, 0));
+        assert_eq!((s.top, s.bottom), (0, 1));
+    }
+
+    #[test]
+    fn decsc_and_decrc() {
+        let mut s = screen(10, 5, "\x1b[2;3H\x1b[1m\x1b(0\x1b7");
+        run(&mut s, "\x1b[5;5H\x1b[0m\x1b(B\x1b8q");
+        assert_eq!(cursor(&s), (1, 3));
+        let cell = s.lines[1].cells[2];
+        assert_eq!(cell.c, '─');
+        assert!(cell.attrs.bold);
+
+        // The ANSI forms do the same.
+        run(&mut s, "\x1b[4;4H\x1b[s\x1b[H\x1b[u");
+        assert_eq!(cursor(&s), (3, 3));
+
+        // Restoring without a save goes home with default attributes.
+        let s = screen(10, 5, "\x1b[3;3H\x1b[1m\x1b8x");
+        assert_eq!(cursor(&s), (0, 1));
+        assert!(!s.lines[0].cells[0].attrs.bold);
+
+        // The position is clamped to a smaller screen.
+        let mut s = screen(10, 5, "\x1b[5;10H\x1b7");
+        s.resize(5, 3);
+        run(&mut s, "\x1b8");
+        assert_eq!(cursor(&s), (2, 4));
+    }
+
+    #[test]
+    fn status_reports() {
+        let mut s = screen(10, 5, "\x1b[5n");
+        assert_eq!(s.take_responses(), "\x1b[0n");
+        assert_eq!(s.take_responses(), "");
+
+        run(&mut s, "\x1b[3;4H\x1b[6n");
+        assert_eq!(s.take_responses(), "\x1b[3;4R");
+
+        run(&mut s, "\x1b[c\x1b[0c");
+        assert_eq!(s.take_responses(), "\x1b[?62;22c\x1b[?62;22c");
+        run(&mut s, "\x1b[>c");
+        assert_eq!(s.take_responses(), "\x1b[>1;10;0c");
+        run(&mut s, "\x1b[1c\x1b[99n");
+        assert_eq!(s.take_responses(), "");
+    }
+
+    #[test]
+    fn dec_special_graphics() {
+        let s = screen(10, 2, "\x1b(0lqqk\r\nx`ax\x1b(Blq");
+        assert_eq!(text(&s), ["┌──┐", "│◆▒│lq"]);
+
+        // G1 is used while shifted out.
+        let s = screen(10, 1, "\x1b)0q\x0eq\x0fq");
+        assert_eq!(text(&s), ["q─q"]);
+    }
+
+    #[test]
+    fn rep_repeats_the_last_character() {
+        let s = screen(10, 1, "ab\x1b[3b");
+        assert_eq!(text(&s), ["abbbb"]);
+    }
+
+    #[test]
+    fn alternate_screen_keeps_the_primary_contents() {
+        let mut s = screen(5, 2, "abc\x1b[?1049h");
+        assert!(s.alternate());
+        assert_eq!(text(&s), ["", ""]);
+        run(&mut s, "xyz\x1b[?1049l");
+        assert!(!s.alternate());
+        assert_eq!(text(&s), ["abc", ""]);
+        assert_eq!(cursor(&s), (0, 3));
+    }
+
+    #[test]
+    fn ris_resets_everything_but_the_title() {
+        let s = screen(5, 3, "\x1b]2;t\x07ab\x1b[2;3r\x1b[?7l\x1bc");
+        assert_eq!(text(&s), ["", "", ""]);
+        assert_eq!(cursor(&s), (0, 0));
+        assert_eq!((s.top, s.bottom), (0, 2));
+        assert!(s.modes.autowrap);
+        assert_eq!(s.title, "t");
+    }
+}
@@ -0,0 +1,312 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::parser::Perform;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    /// One of the 256 palette entries; 0-15 are the themeable ANSI colors.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn is_default(&self) -> bool {
+        *self == Color::Default
+    }
+}
+
+// Palette entries serialize as numbers and truecolor as "#rrggbb", leaving
+// the frontend to map the palette through its theme.
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Color::Default => serializer.serialize_none(),
+            Color::Indexed(index) => serializer.serialize_u8(index),
+            Color::Rgb(r, g, b) => serializer.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Indexed(u8),
+            Rgb(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Indexed(index) => Ok(Color::Indexed(index)),
+            Repr::Rgb(hex) => {
+                let channel = |i: usize| {
+                    hex.get(i..i + 2)
+                        .and_then(|c| u8::from_str_radix(c, 16).ok())
+                        .ok_or_else(|| serde::de::Error::custom(format!("invalid color {hex:?}")))
+                };
+                Ok(Color::Rgb(channel(1)?, channel(3)?, channel(5)?))
+            }
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Graphic rendition of a cell. Only non-default fields are serialized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Attrs {
+    #[serde(skip_serializing_if = "Color::is_default")]
+    pub fg: Color,
+    #[serde(skip_serializing_if = "Color::is_default")]
+    pub bg: Color,
+    #[serde(skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub dim: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub underline: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub inverse: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub hidden: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub strikethrough: bool,
+}
+
+impl Attrs {
+    /// Applies an SGR (`CSI ... m`) sequence.
+    pub fn apply_sgr(&mut self, params: &[Vec<u16>]) {
+        if params.is_empty() {
+            *self = Attrs::default();
+            return;
+        }
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            match param[0] {
+                0 => *self = Attrs::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = param.get(1) != Some(&0),
+                7 => self.inverse = true,
+                8 => self.hidden = true,
+                9 => self.strikethrough = true,
+                21 => self.underline = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                28 => self.hidden = false,
+                29 => self.strikethrough = false,
+                n @ 30..=37 => self.fg = Color::Indexed((n - 30) as u8),
+                38 => self.fg = extended_color(param, &mut params).unwrap_or(self.fg),
+                39 => self.fg = Color::Default,
+                n @ 40..=47 => self.bg = Color::Indexed((n - 40) as u8),
+                48 => self.bg = extended_color(param, &mut params).unwrap_or(self.bg),
+                49 => self.bg = Color::Default,
+                // Underline color isn't rendered, but its arguments must be skipped.
+                58 => {
+                    extended_color(param, &mut params);
+                }
+                n @ 90..=97 => self.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => self.bg = Color::Indexed((n - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads the color after 38/48/58, in either the `;`-separated form
+/// (`38;5;n`, `38;2;r;g;b`) or the `:` form (`38:5:n`, `38:2::r:g:b`).
+fn extended_color<'a>(
+    param: &[u16],
+    rest: &mut impl Iterator<Item = &'a Vec<u16>>,
+) -> Option<Color> {
+    let args: Vec<u16> = if param.len() > 1 {
+        param[1..].to_vec()
+    } else {
+        let kind = rest.next()?[0];
+        let count = if kind == 2 { 3 } else { 1 };
+        std::iter::once(kind)
+            .chain(rest.take(count).map(|p| p[0]))
+            .collect()
+    };
+    let byte = |value: u16| value.min(255) as u8;
+    match args.as_slice() {
+        [5, index, ..] => Some(Color::Indexed(byte(*index))),
+        // The colon form may carry a color space id before the components.
+        [2, _, r, g, b, ..] if param.len() > 5 => Some(Color::Rgb(byte(*r), byte(*g), byte(*b))),
+        [2, r, g, b, ..] => Some(Color::Rgb(byte(*r), byte(*g), byte(*b))),
+        _ => None,
+    }
+}
+
+/// A run of text sharing one style.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub text: String,
+    #[serde(flatten)]
+    pub attrs: Attrs,
+}
+
+/// Appends `text` to `spans`, extending the last span when the style matches.
+pub fn push_span(spans: &mut Vec<Span>, text: &str, attrs: Attrs) {
+    match spans.last_mut() {
+        Some(last) if last.attrs == attrs => last.text.push_str(text),
+        _ => spans.push(Span {
+            text: text.to_string(),
+            attrs,
+        }),
+    }
+}
+
+/// Turns a stream of program output into styled spans for line-oriented
+/// display, keeping SGR styling and dropping every other control sequence.
+/// Whatever a program draws on the alternate screen is left out.
+#[derive(Default)]
+pub struct SpanWriter {
+    attrs: Attrs,
+    spans: Vec<Span>,
+    alternate: bool,
+}
+
+impl SpanWriter {
+    pub fn take_spans(&mut self) -> Vec<Span> {
+        std::mem::take(&mut self.spans)
+    }
+}
+
+impl Perform for SpanWriter {
+    fn print(&mut self, c: char) {
+        if !self.alternate {
+            push_span(&mut self.spans, c.encode_utf8(&mut [0; 4]), self.attrs);
+        }
+    }
+
+    fn execute(&mut self, c: char) {
+        if let '\n' | '\t' = c {
+            self.print(c);
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &[Vec<u16>], intermediates: &[char], action: char) {
+        match (intermediates, action) {
+            ([], 'm') => self.attrs.apply_sgr(params),
+            (['?'], 'h' | 'l') if params.iter().any(|p| matches!(p[0], 47 | 1047 | 1049)) => {
+                self.alternate = action == 'h';
+            }
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, _intermediates: &[char], _action: char) {}
+
+    fn osc_dispatch(&mut self, _data: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::Parser;
+    use super::*;
+
+    /// Applies `CSI params m` on top of `attrs`.
+    fn sgr(attrs: Attrs, params: &str) -> Attrs {
+        let mut writer = SpanWriter {
+            attrs,
+            ..SpanWriter::default()
+        };
+        Parser::default().advance(&mut writer, &format!("\x1b[{params}m"));
+        writer.attrs
+    }
+
+    fn fg(params: &str) -> Color {
+        sgr(Attrs::default(), params).fg
+    }
+
+    #[test]
+    fn reads_palette_colors() {
+        assert_eq!(fg("38;5;196"), Color::Indexed(196));
+        assert_eq!(fg("38:5:9"), Color::Indexed(9));
+        assert_eq!(sgr(Attrs::default(), "48;5;21").bg, Color::Indexed(21));
+        assert_eq!(fg("31"), Color::Indexed(1));
+        assert_eq!(fg("97"), Color::Indexed(15));
+    }
+
+    #[test]
+    fn reads_truecolor() {
+        let attrs = sgr(Attrs::default(), "48;2;10;20;30");
+        assert_eq!(attrs.bg, Color::Rgb(10, 20, 30));
+        assert_eq!(attrs.fg, Color::Default);
+        // Parameters after the color are still applied.
+        let attrs = sgr(Attrs::default(), "38;2;1;2;3;1");
+        assert_eq!(attrs.fg, Color::Rgb(1, 2, 3));
+        assert!(attrs.bold);
+    }
+
+    #[test]
+    fn reads_the_colon_form_with_or_without_a_color_space() {
+        assert_eq!(fg("38:2::10:20:30"), Color::Rgb(10, 20, 30));
+        assert_eq!(fg("38:2:0:10:20:30"), Color::Rgb(10, 20, 30));
+        assert_eq!(fg("38:2:10:20:30"), Color::Rgb(10, 20, 30));
+        // Sub-parameters don't swallow the next parameter.
+        let attrs = sgr(Attrs::default(), "38:2::1:2:3;4");
+        assert_eq!(attrs.fg, Color::Rgb(1, 2, 3));
+        assert!(attrs.underline);
+    }
+
+    #[test]
+    fn keeps_the_color_on_truncated_sequences() {
+        let red = Attrs {
+            fg: Color::Indexed(1),
+            ..Attrs::default()
+        };
+        for params in ["38", "38;5", "38;2;1;2", "38:2:1:2", "38:5", "38;7;1"] {
+            assert_eq!(sgr(red, params), red, "{params}");
+        }
+    }
+
+    #[test]
+    fn clamps_out_of_range_components() {
+        assert_eq!(fg("38;5;300"), Color::Indexed(255));
+        assert_eq!(fg("38;2;256;0;999"), Color::Rgb(255, 0, 255));
+    }
+
+    #[test]
+    fn skips_underline_color_arguments() {
+        let attrs = sgr(Attrs::default(), "58;2;1;2;3;1");
+        assert_eq!(attrs.fg, Color::Default);
+        assert!(attrs.bold);
+        assert_eq!(sgr(Attrs::default(), "58;5;4"), Attrs::default());
+    }
+
+    #[test]
+    fn resets_attributes() {
+        let styled = sgr(Attrs::default(), "1;2;3;38;5;1;48;2;1;2;3");
+        assert!(styled.bold && styled.dim && styled.italic);
+
+        let attrs = sgr(styled, "22");
+        assert!(!attrs.bold && !attrs.dim);
+        assert!(attrs.italic);
+        assert_eq!(attrs.fg, styled.fg);
+
+        let attrs = sgr(styled, "39");
+        assert_eq!(attrs.fg, Color::Default);
+        assert_eq!(attrs.bg, styled.bg);
+
+        let attrs = sgr(styled, "49");
+        assert_eq!(attrs.bg, Color::Default);
+        assert_eq!(attrs.fg, styled.fg);
+
+        assert_eq!(sgr(styled, "0"), Attrs::default());
+        assert_eq!(sgr(styled, ""), Attrs::default());
+    }
+}
@@ -1,6 +1,10 @@
 import React, { useState, useRef, useEffect } from 'react';
-import { invoke } from '@tauri-apps/api/core';
+import { invoke, Channel } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { PerformanceMonitor } from './PerformanceMonitor';
+import { ScrollbackBlock } from './ScrollbackBlock';
+import { ScreenView, ScreenSnapshot } from './ScreenView';
+import { BinaryBlock, BinarySummary } from './BinaryBlock';
 
 interface CommandLog {
   timestamp: number;
@@ -23,12 +27,190 @@ interface PerformanceMetrics {
 interface TerminalLine {
   type: 'output' | 'error' | 'command';
   text: string;
+  // 会话命令的输出只记录其在后端 scrollback 中的行范围
+  scrollback?: { session: number; first: number; count: number };
+  // 二进制输出只显示摘要，dir 为保存时的默认目录
+  binary?: { summary: BinarySummary; dir: string };
   meta?: {
     dir: string;
     branch?: string;
   };
 }
 
+interface CommandResult {
+  stdout: string;
+  stderr: string;
+  stdoutBinary: BinarySummary | null;
+  stderrBinary: BinarySummary | null;
+  exitCode: number | null;
+  signal: number | null;
+  durationMs: number;
+  startedAt: number;
+}
+
+type CommandError =
+  | { kind: 'SpawnFailed'; program: string; message: string }
+  | { kind: 'NotFound'; program: string }
+  | { kind: 'PermissionDenied'; path: string }
+  | ({ kind: 'NonZeroExit' } & CommandResult)
+  | { kind: 'InvalidCwd'; path: string }
+  | { kind: 'InvalidEnv'; key: string }
+  | { kind: 'UnknownEncoding'; label: string }
+  | { kind: 'Timeout'; afterMs: number }
+  | { kind: 'Cancelled' }
+  | { kind: 'UnknownProfile'; name: string }
+  | { kind: 'InvalidProfileName'; name: string }
+  | { kind: 'AliasCycle'; chain: string[] }
+  | { kind: 'InvalidAlias'; name: string }
+  | { kind: 'UnknownAlias'; name: string }
+  | { kind: 'NoShellIntegration'; program: string }
+  | { kind: 'UnknownId'; id: number }
+  | { kind: 'Io'; message: string };
+
+interface SessionInfo {
+  id: number;
+  cwd: string;
+  env: { [key: string]: string };
+  shell: { program: string; args: string[] }; // 实际启动的 shell（完整路径）
+  encoding: string; // 'auto' 或输出编码名称（GBK、Shift_JIS、windows-1252 等）
+  inputEncoding: string;
+  integrated: boolean; // 没有 shell 集成（REPL、ssh）时只能作为屏幕直接输入
+  profile: string | null;
+  title: string | null;
+  theme: string | null;
+}
+
+// 后端保存的启动配置，如 "bash login"、"python3 REPL"、"ssh build-box"
+interface Profile {
+  name: string;
+  program: string | null;
+  args: string[];
+  cwd: string | null;
+  env: { [key: string]: string };
+  theme: string | null;
+  title: string | null;
+  aliases: { [name: string]: string };
+}
+
+// 会话中生效的别名；后面的来源覆盖前面的：config < profile < project < session
+interface Alias {
+  name: string;
+  value: string;
+  source: 'config' | 'profile' | 'project' | 'session';
+}
+
+// 缩写展开后的输入行；cursor 与 <input> 的 selectionStart 一样按 UTF-16 计
+interface Expansion {
+  line: string;
+  cursor: number;
+}
+
+interface RunOutput {
+  firstLine: number;
+  lineCount: number;
+}
+
+interface SessionRunResult extends RunOutput {
+  binary: BinarySummary | null;
+  exitCode: number;
+  cwd: string;
+  durationMs: number;
+  startedAt: number;
+  project: ProjectStatus | null;
+}
+
+// 会话所在项目的 .quickterm.toml；新的或修改过的文件需用户确认信任后才会应用
+interface ProjectStatus {
+  path: string;
+  hash: string;
+  trust: 'trusted' | 'untrusted' | 'changed' | 'denied';
+  config: {
+    aliases: { [name: string]: string };
+    env: { [key: string]: string };
+    startup: string[];
+  };
+  error: ConfigError | null;
+  output: RunOutput | null; // 刚应用时启动命令的输出范围
+}
+
+function describeProject(status: ProjectStatus): string {
+  const { aliases, env, startup } = status.config;
+  const sections = [
+    status.trust === 'changed' ? `项目配置已修改：${status.path}` : `发现项目配置：${status.path}`,
+  ];
+  if (Object.keys(env).length) {
+    sections.push('环境变量：\n' + Object.entries(env).map(([k, v]) => `  ${k}=${v}`).join('\n'));
+  }
+  if (Object.keys(aliases).length) {
+    sections.push('别名：\n' + Object.entries(aliases).map(([k, v]) => `  ${k} = ${v}`).join('\n'));
+  }
+  if (startup.length) {
+    sections.push('启动命令：\n' + startup.map((c) => `  ${c}`).join('\n'));
+  }
+  sections.push('信任并应用这些设置吗？');
+  return sections.join('\n\n');
+}
+
+// .quicktermrc 中的配置（后端解析、校验并补全默认值）
+interface Config {
+  shell: { program: string | null; args: string[] };
+  env: { [key: string]: string };
+  aliases: { [name: string]: string };
+  abbreviations: { [name: string]: string };
+  theme: {
+    name: string | null;
+    background: string | null;
+    foreground: string | null;
+    fontFamily: string | null;
+    fontSize: number | null;
+  };
+  keybindings: { [action: string]: string }; // interrupt / eof / clear / toggle-performance -> "ctrl+l"
+  history: { maxEntries: number; ignoreDuplicates: boolean; ignoreSpace: boolean };
+}
+
+interface ConfigError {
+  path: string;
+  line: number | null;
+  column: number | null;
+  message: string;
+}
+
+interface LoadedConfig {
+  path: string | null;
+  config: Config;
+  error: ConfigError | null;
+  watchError: ConfigError | null;
+}
+
+// 配置文件修改后后端推送的变化，key 如 ['aliases', 'gco']、['theme', 'fontSize']
+interface ConfigChanged {
+  config: Config;
+  changes: { key: string[]; old: unknown; new: unknown }[];
+}
+
+function describeConfigError(err: ConfigError): string {
+  const at = err.line !== null ? `${err.path}:${err.line}:${err.column}` : err.path;
+  return `${at}: ${err.message}`;
+}
+
+const KEY_NAMES: { [name: string]: string } = {
+  enter: 'Enter', tab: 'Tab', escape: 'Escape', space: ' ', backspace: 'Backspace',
+  delete: 'Delete', insert: 'Insert', up: 'ArrowUp', down: 'ArrowDown', left: 'ArrowLeft',
+  right: 'ArrowRight', home: 'Home', end: 'End', pageup: 'PageUp', pagedown: 'PageDown',
+};
+
+// 按键是否匹配配置中的组合键（后端已规范化为 "ctrl+shift+k" 形式）
+function matchesChord(e: React.KeyboardEvent, chord: string | undefined): boolean {
+  if (!chord) return false;
+  const parts = chord.split('+');
+  const key = parts.pop()!;
+  return e.ctrlKey === parts.includes('ctrl')
+    && e.altKey === parts.includes('alt')
+    && e.shiftKey === parts.includes('shift')
+    && e.metaKey === parts.includes('meta')
+    && e.key.toLowerCase() === (KEY_NAMES[key] ?? key).toLowerCase();
+}
+
 // 简单的 ANSI 代码移除函数
 function stripAnsi(str: string): string {
   return str.replace(/\x1B\[[0-9;]*[JKmsu]/g, '')
@@ -36,13 +218,70 @@ function stripAnsi(str: string): string {
             .replace(/\x1B\][0-9];[^\x07]*\x07/g, '');
 }
 
+// 将后端返回的结构化错误转换为可读文本
+function describeError(e: unknown): string {
+  if (typeof e !== 'object' || e === null || !('kind' in e)) {
+    return String(e);
+  }
+  const err = e as CommandError;
+  switch (err.kind) {
+    case 'SpawnFailed':
+      return `failed to start ${err.program}: ${err.message}`;
+    case 'NotFound':
+      return `${err.program}: command not found`;
+    case 'PermissionDenied':
+      return `${err.path}: permission denied`;
+    case 'NonZeroExit':
+      return err.stderr || err.stdout || `exit status ${err.exitCode ?? err.signal}`;
+    case 'InvalidCwd':
+      return `${err.path}: not a directory`;
+    case 'InvalidEnv':
+      return `invalid environment variable ${JSON.stringify(err.key)}`;
+    case 'UnknownEncoding':
+      return `unknown encoding ${JSON.stringify(err.label)}`;
+    case 'Timeout':
+      return `command timed out after ${err.afterMs}ms`;
+    case 'Cancelled':
+      return 'command was cancelled';
+    case 'UnknownProfile':
+      return `no profile named ${JSON.stringify(err.name)}`;
+    case 'InvalidProfileName':
+      return `invalid profile name ${JSON.stringify(err.name)}`;
+    case 'AliasCycle':
+      return `alias loop: ${err.chain.join(' -> ')}`;
+    case 'InvalidAlias':
+      return `invalid alias ${JSON.stringify(err.name)}`;
+    case 'UnknownAlias':
+      return `no alias named ${JSON.stringify(err.name)}`;
+    case 'NoShellIntegration':
+      return `${err.program} does not support running commands in a session`;
+    case 'UnknownId':
+      return `no running process with id ${err.id}`;
+    case 'Io':
+      return err.message;
+  }
+}
+
+// 执行一次性命令，非零退出时后端返回 NonZeroExit 错误
+// 工作目录作为参数传给后端，不再拼接进 shell 命令
+// background: 提示符等后台查询，超时后由后端结束进程（默认超时可配置）
+// id: 先用 reserve_command_id 取得，结果返回前可用 cancel_command 结束命令
+async function runCommand(
+  command: string,
+  options: { cwd?: string; timeoutMs?: number; background?: boolean; id?: number } = {}
+): Promise<string> {
+  const result = await invoke<CommandResult>('execute_command', { command, ...options });
+  return result.stdout;
+}
+
 function App() {
   const [input, setInput] = useState('');
   const [output, setOutput] = useState<TerminalLine[]>([]);
   const [isLoading, setIsLoading] = useState(false);
   const [currentDir, setCurrentDir] = useState<string>(''); // 当前目录状态
-  const [previousDir, setPreviousDir] = useState<string>(''); // 上一个目录状态 (用于 cd -)
   const [gitBranch, setGitBranch] = useState<string>(''); // Git 分支状态
+  const [lastExitCode, setLastExitCode] = useState<number | null>(0); // 上一条命令的退出码
+  const [screen, setScreen] = useState<ScreenSnapshot | null>(null); // 全屏程序（vim/less 等）的屏幕
   
   // ✅ 新增：命令历史状态
   const [commandHistory, setCommandHistory] = useState<string[]>([]);
@@ -55,6 +294,15 @@ function App() {
   const [showPerfMonitor, setShowPerfMonitor] = useState(false);
 
   const bottomRef = useRef<HTMLDivElement>(null);
+  const outputRef = useRef<HTMLDivElement>(null);
+  const inputRef = useRef<HTMLInputElement>(null);
+  // ✅ 持久会话 id，以及是否有命令正在会话中运行（用于 Ctrl+C）
+  const sessionId = useRef<number | null>(null);
+  const runningInSession = useRef(false);
+  // ✅ 配置文件内容，加载前为 null
+  const [config, setConfig] = useState<Config | null>(null);
+  // ✅ 本次运行中已询问过的项目文件（按内容哈希）
+  const askedProjects = useRef<Set<string>>(new Set());
 
   // ✅ 加载命令历史和日志
   useEffect(() => {
@@ -97,10 +345,9 @@ function App() {
   const logCommand = (
     command: string,
     success: boolean,
-    outputLines: number
+    outputLines: number,
+    duration: number = Date.now() - commandStartTime
   ) => {
-    const duration = Date.now() - commandStartTime;
-    
     const log: CommandLog = {
       timestamp: Date.now(),
       command,
@@ -192,33 +439,201 @@ ${topCommands}
   // 获取 Git 分支
   const updateGitBranch = async (dir: string) => {
     try {
-      const result = await invoke<string>('execute_command', { 
-        command: `cd "${dir}" && git branch --show-current 2>/dev/null` 
-      });
+      const result = await runCommand('git branch --show-current', { cwd: dir, background: true });
       setGitBranch(stripAnsi(result.trim()));
     } catch {
       setGitBranch('');
     }
   };
 
-  // 初始化：获取当前目录
+  // ✅ 根据输出区域大小和字体宽度计算终端行列数
+  const terminalSize = () => {
+    const el = outputRef.current;
+    const ctx = document.createElement('canvas').getContext('2d');
+    if (!el || !ctx) return { cols: 80, rows: 24 };
+    const style = getComputedStyle(el);
+    ctx.font = style.font;
+    const charWidth = ctx.measureText('M').width || 8;
+    const lineHeight = parseFloat(style.lineHeight) || 20;
+    return {
+      cols: Math.max(20, Math.floor(el.clientWidth / charWidth)),
+      rows: Math.max(5, Math.floor(el.clientHeight / lineHeight)),
+    };
+  };
+
+  // ✅ 窗口大小变化时通知后端，子进程收到 SIGWINCH 后重新排版
+  useEffect(() => {
+    const onResize = () => {
+      if (sessionId.current === null) return;
+      invoke('resize_session', { id: sessionId.current, ...terminalSize() }).catch(() => {});
+    };
+    window.addEventListener('resize', onResize);
+    return () => window.removeEventListener('resize', onResize);
+  }, []);
+
+  // ✅ 切换到新会话：关闭旧会话，同步目录、窗口标题和主题
+  const adoptSession = async (session: SessionInfo) => {
+    const previous = sessionId.current;
+    sessionId.current = session.id;
+    if (previous !== null && previous !== session.id) {
+      invoke('close_session', { id: previous }).catch(() => {});
+    }
+    setScreen(null);
+    document.title = session.title ?? 'my-terminal';
+    if (session.theme) {
+      document.documentElement.dataset.theme = session.theme;
+    } else {
+      delete document.documentElement.dataset.theme;
+    }
+    setCurrentDir(session.cwd);
+    await updateGitBranch(session.cwd);
+    if (session.integrated) {
+      try {
+        await handleProject(await invoke<ProjectStatus | null>('check_project', { id: session.id }), session.id);
+      } catch (e) {
+        console.error('Failed to check project config:', e);
+      }
+    }
+  };
+
+  // ✅ 项目配置：已信任的由后端自动应用；新的或修改过的先询问用户
+  const handleProject = async (status: ProjectStatus | null, session: number) => {
+    if (!status) return;
+    if (status.output) {
+      appendOutput('output', `[已应用项目配置 ${status.path}]\n`);
+      if (status.output.lineCount > 0) {
+        showScrollback(session, status.output.firstLine, status.output.lineCount);
+      }
+    }
+    if (status.error) {
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'error',
+        text: `项目配置有误：${describeConfigError(status.error!)}`
+      }]);
+      return;
+    }
+    const ask = status.trust === 'untrusted' || status.trust === 'changed';
+    if (!ask || askedProjects.current.has(status.hash)) return;
+    askedProjects.current.add(status.hash);
+    const trusted = window.confirm(describeProject(status));
+    try {
+      const updated = await invoke<ProjectStatus | null>('trust_project', {
+        id: session,
+        path: status.path,
+        hash: status.hash,
+        trusted,
+      });
+      await handleProject(updated, session);
+    } catch (e) {
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
+    }
+  };
+
+  // 初始化：创建持久会话，并获取当前目录
+  useEffect(() => {
+    let disposed = false;
+    const initSession = async () => {
+      try {
+        const loaded = await invoke<LoadedConfig>('get_config');
+        setConfig(loaded.config);
+        if (loaded.error) {
+          setOutput((prev: TerminalLine[]) => [...prev, {
+            type: 'error',
+            text: `配置文件有误，已使用默认配置：${describeConfigError(loaded.error!)}`
+          }]);
+        }
+        if (loaded.watchError) {
+          setOutput((prev: TerminalLine[]) => [...prev, {
+            type: 'error',
+            text: `无法监视配置文件，修改后需重启才能生效：${describeConfigError(loaded.watchError!)}`
+          }]);
+        }
+      } catch (e) {
+        console.error('Failed to load config:', e);
+      }
+      try {
+        const session = await invoke<SessionInfo>('create_session', terminalSize());
+        if (disposed) {
+          invoke('close_session', { id: session.id }).catch(() => {});
+          return;
+        }
+        await adoptSession(session);
+      } catch (e) {
+        console.error('Failed to create session:', e);
+      }
+    };
+    initSession();
+
+    return () => {
+      disposed = true;
+      if (sessionId.current !== null) {
+        invoke('close_session', { id: sessionId.current }).catch(() => {});
+        sessionId.current = null;
+      }
+    };
+  }, []);
+
+  // ✅ 全屏程序进入/退出备用屏幕：后端推送屏幕快照，退出时为 null 并恢复命令块视图
+  useEffect(() => {
+    const unlisten = listen<{ id: number; screen: ScreenSnapshot | null }>('session-screen', (event) => {
+      if (event.payload.id === sessionId.current) {
+        setScreen(event.payload.screen);
+      }
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  // ✅ 配置文件热重载：应用新配置；有误时继续使用之前的配置并提示
   useEffect(() => {
-    const initDir = async () => {
+    const changed = listen<ConfigChanged>('config-changed', (event) => {
+      const { config, changes } = event.payload;
+      setConfig(config);
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'output',
+        text: changes.length
+          ? `[配置已重新加载：${changes.map((c) => c.key.join('.')).join(', ')}]`
+          : '[配置已恢复]'
+      }]);
+    });
+    const failed = listen<ConfigError>('config-error', (event) => {
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'error',
+        text: `配置文件有误，继续使用之前的配置：${describeConfigError(event.payload)}`
+      }]);
+    });
+    return () => {
+      changed.then((stop) => stop());
+      failed.then((stop) => stop());
+    };
+  }, []);
+
+  // ✅ 当前会话退出（exit、REPL 或 ssh 结束）后打开新的默认会话
+  useEffect(() => {
+    const unlisten = listen<{ id: number; exitCode: number | null }>('session-exit', async (event) => {
+      if (event.payload.id !== sessionId.current) return;
+      sessionId.current = null;
+      setScreen(null);
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'output',
+        text: `[会话已结束，退出码 ${event.payload.exitCode ?? '未知'}]\n`
+      }]);
       try {
-        const dir = await invoke<string>('execute_command', { 
-          command: 'cd ~ && pwd' 
-        });
-        const cleanDir = stripAnsi(dir.trim());
-        setCurrentDir(cleanDir);
-        setPreviousDir(cleanDir);
-        await updateGitBranch(cleanDir);
+        await adoptSession(await invoke<SessionInfo>('create_session', terminalSize()));
       } catch (e) {
-        console.error('Failed to get initial directory:', e);
+        setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
       }
+    });
+    return () => {
+      unlisten.then((stop) => stop());
     };
-    initDir();
   }, []);
 
+  useEffect(() => {
+    if (!screen) inputRef.current?.focus();
+  }, [screen === null]);
+
   useEffect(() => {
     bottomRef.current?.scrollIntoView({ behavior: 'smooth' });
   }, [output]);
@@ -301,16 +716,20 @@ ${topCommands}
   };
 
   // ✅ 添加命令到历史
-  const addToHistory = (cmd: string) => {
-    if (!cmd.trim()) return;
+  const addToHistory = (raw: string) => {
+    const cmd = raw.trim();
+    if (!cmd) return;
+    const settings = config?.history ?? { maxEntries: 100, ignoreDuplicates: true, ignoreSpace: false };
+    // 以空格开头的命令不记录（同 bash 的 ignorespace）
+    if (settings.ignoreSpace && raw.startsWith(' ')) return;
     
     setCommandHistory(prev => {
       // 移除重复的命令
-      const filtered = prev.filter(c => c !== cmd);
+      const filtered = settings.ignoreDuplicates ? prev.filter(c => c !== cmd) : prev;
       // 添加到末尾（最新的）
       const newHistory = [...filtered, cmd];
-      // 只保留最近 100 条
-      return newHistory.slice(-100);
+      // 只保留最近 maxEntries 条
+      return newHistory.slice(-settings.maxEntries);
     });
     
     // 重置历史索引
@@ -318,6 +737,49 @@ ${topCommands}
     setTempInput('');
   };
 
+  // ✅ 追加流式输出：同类型的连续输出合并到同一块
+  const appendOutput = (type: 'output' | 'error', text: string) => {
+    setOutput((prev: TerminalLine[]) => {
+      const last = prev[prev.length - 1];
+      if (last && last.type === type && !last.scrollback && !last.binary) {
+        return [...prev.slice(0, -1), { ...last, text: last.text + text }];
+      }
+      return [...prev, { type, text }];
+    });
+  };
+
+  // ✅ 更新（或创建）命令的输出块，内容由 ScrollbackBlock 按需从后端获取
+  const showScrollback = (session: number, first: number, count: number) => {
+    setOutput((prev: TerminalLine[]) => {
+      const last = prev[prev.length - 1];
+      if (last?.scrollback?.session === session && last.scrollback.first === first) {
+        return [...prev.slice(0, -1), { ...last, scrollback: { session, first, count } }];
+      }
+      return [...prev, { type: 'output', text: '', scrollback: { session, first, count } }];
+    });
+  };
+
+  // ✅ 在持久会话中执行命令：输出边产生边显示，cd/export 等状态由 shell 保留
+  const runInSession = async (command: string) => {
+    const session = sessionId.current;
+    const onOutput = new Channel<RunOutput>();
+    onOutput.onmessage = ({ firstLine, lineCount }) => {
+      if (session !== null) showScrollback(session, firstLine, lineCount);
+    };
+
+    runningInSession.current = true;
+    try {
+      const result = await invoke<SessionRunResult>('run_in_session', {
+        id: session,
+        command,
+        onOutput,
+      });
+      return result;
+    } finally {
+      runningInSession.current = false;
+    }
+  };
+
   const executeCommand = async (cmd: string) => {
     if (!cmd.trim()) return;
     
@@ -327,99 +789,64 @@ ${topCommands}
     setCommandStartTime(Date.now());
 
     // ✅ 添加到历史（在执行前）
-    addToHistory(trimmedCmd);
-
-    // 别名映射
-    const aliases: { [key: string]: string } = {
-      'll': 'ls -la',
-      'la': 'ls -la',
-      'l': 'ls -lh',
-      'ls': 'ls --color=auto',
-      '..': 'cd ..',
-      '...': 'cd ../..',
-      '....': 'cd ../../..',
-      '~': 'cd ~',
-      '-': 'cd -',
-      'md': 'mkdir',
-      'rd': 'rmdir',
-      'cls': 'clear',
-      'c': 'clear',
-      'gs': 'git status',
-      'ga': 'git add',
-      'gc': 'git commit',
-      'gp': 'git push',
-      'gl': 'git log',
-    };
-
-    // 展开别名
-    const cmdParts = trimmedCmd.split(' ');
-    const baseCmd = cmdParts[0];
-    
-    if (aliases[baseCmd]) {
-      cmdParts[0] = aliases[baseCmd];
-      trimmedCmd = cmdParts.join(' ');
-    }
+    addToHistory(cmd);
 
-    // 处理 cd 命令
-    if (trimmedCmd.startsWith('cd ') || trimmedCmd === 'cd') {
-      const outputBeforeCount = output.length;
-      // 记录输入的命令
-      setOutput((prev: TerminalLine[]) => [...prev, { 
-        type: 'command', 
+    // ✅ alias / unalias 命令 - 在展开别名之前处理，别名只在当前会话有效
+    // alias                  列出生效的别名
+    // alias gco              显示某个别名
+    // alias gco='git checkout $1'
+    // unalias gco            隐藏别名（包括配置文件、profile、项目中的）
+    const [builtin] = trimmedCmd.split(/\s+/, 1);
+    if (builtin === 'alias' || builtin === 'unalias') {
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'command',
         text: cmd,
-        // 存储当前环境信息用于显示
         meta: { dir: getDisplayPath(currentDir), branch: gitBranch }
       } as any]);
-      setInput('');
-      
-      let targetDir = trimmedCmd === 'cd' ? '~' : trimmedCmd.substring(3).trim() || '~';
-      
+
+      const arg = trimmedCmd.slice(builtin.length).trim();
       try {
-        let testCmd = '';
-        if (targetDir === '-') {
-          if (previousDir) {
-            targetDir = previousDir;
-            testCmd = `cd "${targetDir}" && pwd`;
-          } else {
-            setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: 'cd: OLDPWD not set' }]);
-            logCommand(trimmedCmd, false, 1);
-            return;
-          }
-        } else if (targetDir === '~' || targetDir === '') {
-          testCmd = 'cd && pwd';
-        } else if (targetDir.startsWith('~')) {
-          const pathAfterTilde = targetDir.substring(1);
-          testCmd = `cd "$HOME${pathAfterTilde}" && pwd`;
-        } else if (targetDir.startsWith('/') || /^[a-zA-Z]:\\/.test(targetDir)) {
-          testCmd = `cd "${targetDir}" && pwd`;
+        if (builtin === 'unalias') {
+          await invoke('remove_alias', { id: sessionId.current, name: arg });
+        } else if (arg.includes('=')) {
+          await invoke('define_alias', { id: sessionId.current, definition: arg });
         } else {
-          testCmd = currentDir ? `cd "${currentDir}" && cd "${targetDir}" && pwd` : `cd "${targetDir}" && pwd`;
+          const aliases = await invoke<Alias[]>('list_aliases', { id: sessionId.current });
+          const shown = arg ? aliases.filter((a) => a.name === arg) : aliases;
+          if (arg && !shown.length) {
+            throw { kind: 'UnknownAlias', name: arg };
+          }
+          setOutput((prev: TerminalLine[]) => [...prev, {
+            type: 'output',
+            text: shown
+              .map((a) => `${a.name.padEnd(8, ' ')}  ${a.value}${a.source === 'config' ? '' : `  (${a.source})`}`)
+              .join('\n')
+          }]);
         }
-
-        const result = await invoke<string>('execute_command', { 
-          command: testCmd 
-        });
-        
-        const newDir = stripAnsi(result.trim());
-        setPreviousDir(currentDir);
-        setCurrentDir(newDir);
-        await updateGitBranch(newDir);
-        
-        // cd 成功后通常不显示输出，但添加一个空行
-        setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
-        
-        // ✅ 记录性能
         logCommand(trimmedCmd, true, 1);
-      } catch (error) {
-        setOutput((prev: TerminalLine[]) => [...prev, { 
-          type: 'error', 
-          text: `cd: ${targetDir}: No such file or directory` 
-        }]);
-        setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
-        
-        // ✅ 记录性能 (失败)
-        logCommand(trimmedCmd, false, 1);
+      } catch (e) {
+        setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
+        logCommand(trimmedCmd, false, 0);
       }
+
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
+      setInput('');
+      return;
+    }
+
+    // 展开别名（后端处理 $1/$@、链式别名和 ; && | 之后的命令）
+    try {
+      trimmedCmd = await invoke<string>('expand_aliases', { id: sessionId.current, line: trimmedCmd });
+    } catch (e) {
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'command',
+        text: cmd,
+        meta: { dir: getDisplayPath(currentDir), branch: gitBranch }
+      } as any]);
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
+      setInput('');
+      logCommand(trimmedCmd, false, 0);
       return;
     }
 
@@ -530,41 +957,85 @@ ${topCommands}
       return;
     }
 
-    // ✅ 智能路径检测 (方案 3)
-    // 只检测简单的目录名（字母、数字、-、_、.）
-    const isDirPattern = /^[a-zA-Z0-9_.-]+$/.test(trimmedCmd);
-    
-    if (isDirPattern) {
+    // ✅ encoding 命令 - 查看或设置会话的输出/输入编码
+    // encoding            显示当前编码
+    // encoding gbk        输出按 GBK 解码，输入也按 GBK 编码
+    // encoding auto       自动检测（默认）
+    // encoding gbk utf-8  输出 GBK，输入 UTF-8
+    if (trimmedCmd === 'encoding' || trimmedCmd.startsWith('encoding ')) {
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'command',
+        text: cmd,
+        meta: { dir: getDisplayPath(currentDir), branch: gitBranch }
+      } as any]);
+
+      const [, encoding, inputEncoding] = trimmedCmd.split(/\s+/);
       try {
-        // 尝试作为目录跳转
-        const testCmd = currentDir 
-          ? `cd "${currentDir}" && cd "${trimmedCmd}" && pwd`
-          : `cd "${trimmedCmd}" && pwd`;
-        
-        const result = await invoke<string>('execute_command', { 
-          command: testCmd 
-        });
-        
-        // 成功！是一个目录
-        const newDir = stripAnsi(result.trim());
-        setPreviousDir(currentDir);
-        setCurrentDir(newDir);
-        await updateGitBranch(newDir);
-        
-        // 记录输入的跳转命令并添加空行
-        setOutput((prev: TerminalLine[]) => [...prev, { 
-          type: 'command', 
-          text: cmd,
-          meta: { dir: getDisplayPath(currentDir), branch: gitBranch }
-        } as any, { type: 'output', text: '' }]);
-        
-        setInput('');
+        const info = encoding
+          ? await invoke<SessionInfo>('set_session_encoding', { id: sessionId.current, encoding, inputEncoding })
+          : await invoke<SessionInfo>('get_session', { id: sessionId.current });
+        setOutput((prev: TerminalLine[]) => [...prev, {
+          type: 'output',
+          text: `output: ${info.encoding}\ninput:  ${info.inputEncoding}`
+        }]);
+        logCommand(trimmedCmd, true, 2);
+      } catch (e) {
+        setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
+        logCommand(trimmedCmd, false, 0);
+      }
+
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
+      setInput('');
+      return;
+    }
+
+    // ✅ profile 命令 - 列出启动配置，或用某个配置打开新会话替换当前会话
+    // profile               列出配置
+    // profile python3 REPL  打开名为 "python3 REPL" 的配置
+    if (trimmedCmd === 'profile' || trimmedCmd.startsWith('profile ')) {
+      setOutput((prev: TerminalLine[]) => [...prev, {
+        type: 'command',
+        text: cmd,
+        meta: { dir: getDisplayPath(currentDir), branch: gitBranch }
+      } as any]);
+
+      const name = trimmedCmd.slice('profile'.length).trim();
+      try {
+        if (name) {
+          const session = await invoke<SessionInfo>('open_profile', { name, ...terminalSize() });
+          await adoptSession(session);
+          setOutput((prev: TerminalLine[]) => [...prev, {
+            type: 'output',
+            text: `已打开配置 ${name}（${session.shell.program}）`
+          }]);
+        } else {
+          const profiles = await invoke<Profile[]>('list_profiles');
+          setOutput((prev: TerminalLine[]) => [...prev, {
+            type: 'output',
+            text: profiles.length
+              ? profiles
+                  .map((p) => `${p.name.padEnd(20, ' ')}  ${[p.program ?? '(login shell)', ...p.args].join(' ')}${p.cwd ? `  [${p.cwd}]` : ''}`)
+                  .join('\n')
+              : '没有配置'
+          }]);
+        }
         logCommand(trimmedCmd, true, 1);
-        return;
       } catch (e) {
-        // 不是目录或跳转失败，继续作为普通命令执行
+        setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
+        logCommand(trimmedCmd, false, 0);
       }
+
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
+      setInput('');
+      return;
     }
+
+    // ✅ 智能路径检测 (方案 3)
+    // 只检测简单的目录名（字母、数字、-、_、.），是目录就跳转，否则作为普通命令执行
+    const isDirPattern = /^[a-zA-Z0-9_.-]+$/.test(trimmedCmd);
+    const sessionCmd = isDirPattern
+      ? `if [ -d ${trimmedCmd} ]; then cd ${trimmedCmd}; else ${trimmedCmd}; fi`
+      : trimmedCmd;
     
     setIsLoading(true);
     setOutput((prev: TerminalLine[]) => [...prev, { 
@@ -572,45 +1043,111 @@ ${topCommands}
       text: cmd,
       meta: { dir: getDisplayPath(currentDir), branch: gitBranch }
     } as any]);
+    // 运行期间输入行用于回答程序的提示
+    setInput('');
     
-    const outputBeforeCount = output.length;
     try {
-      const fullCmd = currentDir ? `cd "${currentDir}" && ${trimmedCmd}` : trimmedCmd;
-      const result = await invoke<string>('execute_command', { command: fullCmd });
-      if (result) {
-        setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: stripAnsi(result) }]);
+      const result = await runInSession(sessionCmd);
+      const binary = result.binary;
+      if (binary) {
+        setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '', binary: { summary: binary, dir: result.cwd } }]);
       }
       // 每个命令后添加空行
       setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
       
-      const outputAfterCount = output.length;
-      logCommand(trimmedCmd, true, outputAfterCount - outputBeforeCount);
+      setLastExitCode(result.exitCode);
+      logCommand(trimmedCmd, result.exitCode === 0, result.lineCount, result.durationMs);
+
+      // 目录由 shell 决定，命令结束后同步
+      if (result.cwd !== currentDir) {
+        setCurrentDir(result.cwd);
+        await updateGitBranch(result.cwd);
+      }
+      if (sessionId.current !== null) {
+        await handleProject(result.project, sessionId.current);
+      }
     } catch (e) {
-      setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: String(e) }]);
+      setOutput((prev: TerminalLine[]) => [...prev, { type: 'error', text: describeError(e) }]);
       setOutput((prev: TerminalLine[]) => [...prev, { type: 'output', text: '' }]);
       logCommand(trimmedCmd, false, 0);
     } finally {
       setIsLoading(false);
-      setInput('');
+    }
+  };
+
+  // ✅ 缩写（如 gco -> git checkout）：输入时就在输入行中展开，历史中记录的是实际命令
+  // 缩写表和展开规则（只展开命令位置上的词）都在后端；这里只用配置判断光标前的词是否可能是缩写
+  // 和后端一样以空白和 ; & | 分词，make;gco、a&&gp 中的 gco、gp 也会交给后端
+  const endsWithAbbreviation = (before: string) =>
+    !!config?.abbreviations[/[^\s;&|]*$/.exec(before)![0]];
+
+  const expandAbbreviation = async (line: string, cursor: number) => {
+    try {
+      return await invoke<Expansion | null>('expand_abbreviation', { line, cursor });
+    } catch (e) {
+      console.error('Failed to expand abbreviation:', e);
+      return null;
     }
   };
 
   const handleKeyDown = (e: React.KeyboardEvent<HTMLInputElement>) => {
-    // Enter - 执行命令
+    // ✅ 命令运行中：Enter 把输入行发给它的 stdin（回答 [y/N]、密码等提示），Ctrl+D 发送 EOF
+    if (runningInSession.current) {
+      if (e.key === 'Enter') {
+        invoke('write_session', { id: sessionId.current, data: `${input}\n` }).catch(() => {});
+        setInput('');
+        return;
+      }
+      if (matchesChord(e, config?.keybindings['eof'])) {
+        e.preventDefault();
+        invoke('write_session', { id: sessionId.current, data: '\x04' }).catch(() => {});
+        return;
+      }
+    }
+
+    // Enter - 执行命令（先展开行尾的缩写）
     if (e.key === 'Enter') {
-      executeCommand(input);
+      const line = input;
+      if (endsWithAbbreviation(line)) {
+        expandAbbreviation(line, line.length).then((expansion) => executeCommand(expansion?.line ?? line));
+      } else {
+        executeCommand(line);
+      }
+      return;
+    }
+
+    // ✅ 空格 - 光标前是缩写时先展开，再插入空格
+    if (e.key === ' ' && !e.ctrlKey && !e.altKey && !e.metaKey) {
+      const el = e.currentTarget;
+      const cursor = el.selectionStart ?? input.length;
+      if (cursor !== el.selectionEnd || !endsWithAbbreviation(input.slice(0, cursor))) return;
+      e.preventDefault();
+      const line = input;
+      expandAbbreviation(line, cursor).then((expansion) => {
+        const next = expansion ?? { line, cursor };
+        setInput(`${next.line.slice(0, next.cursor)} ${next.line.slice(next.cursor)}`);
+        requestAnimationFrame(() => inputRef.current?.setSelectionRange(next.cursor + 1, next.cursor + 1));
+      });
       return;
     }
     
-    // Ctrl+L - 清屏
-    if (e.ctrlKey && e.key === 'l') {
+    // ✅ Ctrl+C（默认，可在配置中修改）- 中断正在运行的命令（由会话终端发送 SIGINT）
+    if (matchesChord(e, config?.keybindings['interrupt']) && runningInSession.current) {
+      e.preventDefault();
+      invoke('write_session', { id: sessionId.current, data: '\x03' }).catch(() => {});
+      appendOutput('error', '^C\n');
+      return;
+    }
+
+    // Ctrl+L（默认）- 清屏
+    if (matchesChord(e, config?.keybindings['clear'])) {
       e.preventDefault();
       setOutput([]);
       return;
     }
 
-    // Ctrl+P - 切换性能监控
-    if (e.ctrlKey && e.key === 'p') {
+    // Ctrl+P（默认）- 切换性能监控
+    if (matchesChord(e, config?.keybindings['toggle-performance'])) {
       e.preventDefault();
       setShowPerfMonitor(prev => !prev);
       return;
@@ -665,11 +1202,28 @@ ${topCommands}
   };
 
   return (
-    <div className="h-screen bg-[#1e2a3a] text-gray-100 p-4 font-mono text-sm overflow-hidden flex flex-col relative">
+    <div
+      className="h-screen bg-[#1e2a3a] text-gray-100 p-4 font-mono text-sm overflow-hidden flex flex-col relative"
+      style={{
+        backgroundColor: config?.theme.background ?? undefined,
+        color: config?.theme.foreground ?? undefined,
+        fontFamily: config?.theme.fontFamily ?? undefined,
+        fontSize: config?.theme.fontSize ?? undefined,
+      }}
+    >
       {/* 性能监控面板 */}
       <PerformanceMonitor logs={commandLogs} show={showPerfMonitor} />
 
-      <div className="flex-1 overflow-auto mb-2 pr-2 select-text">
+      {screen && (
+        <ScreenView
+          screen={screen}
+          onInput={(data) => {
+            invoke('write_session', { id: sessionId.current, data }).catch(() => {});
+          }}
+        />
+      )}
+
+      <div ref={outputRef} className="flex-1 overflow-auto mb-2 pr-2 select-text">
         {output.map((line: TerminalLine, i: number) => (
           <div key={i} className="mb-1">
             {line.type === 'command' && (
@@ -682,9 +1236,20 @@ ${topCommands}
               </div>
             )}
             {line.type === 'output' && (
-              <div className="text-gray-300 whitespace-pre-wrap break-all">
-                {line.text}
-              </div>
+              line.binary ? (
+                <BinaryBlock summary={line.binary.summary} dir={line.binary.dir} />
+              ) : line.scrollback ? (
+                <ScrollbackBlock
+                  sessionId={line.scrollback.session}
+                  first={line.scrollback.first}
+                  count={line.scrollback.count}
+                  scrollRoot={outputRef}
+                />
+              ) : (
+                <div className="text-gray-300 whitespace-pre-wrap break-all">
+                  {line.text}
+                </div>
+              )
             )}
             {line.type === 'error' && (
               <div className="text-red-400 whitespace-pre-wrap break-all font-semibold">
@@ -703,7 +1268,11 @@ ${topCommands}
           {gitBranch && (
             <span className="text-purple-400">({gitBranch})</span>
           )}
+          {lastExitCode !== 0 && (
+            <span className="text-red-400">✗ {lastExitCode ?? 'killed'}</span>
+          )}
           <input
+            ref={inputRef}
             type="text"
             value={input}
             onChange={(e: React.ChangeEvent<HTMLInputElement>) => {
@@ -0,0 +1,68 @@
+import React, { useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+
+// 后端检测到二进制输出时返回的摘要；原始字节保存在后端，可另存为文件
+export interface BinarySummary {
+  id: number;
+  size: number;
+  mimeType: string;
+  extension: string | null;
+  truncated: boolean;
+}
+
+function formatSize(bytes: number): string {
+  const units = ['B', 'KB', 'MB', 'GB', 'TB'];
+  let size = bytes;
+  let unit = 0;
+  while (size >= 1024 && unit < units.length - 1) {
+    size /= 1024;
+    unit++;
+  }
+  return unit === 0 ? `${bytes} B` : `${size.toFixed(1)} ${units[unit]}`;
+}
+
+function describeSaveError(e: unknown): string {
+  const err = e as { kind?: string; path?: string; message?: string };
+  switch (err?.kind) {
+    case 'UnknownId':
+      return '原始输出已被清理，无法保存';
+    case 'PermissionDenied':
+      return `${err.path}: permission denied`;
+    default:
+      return err?.message ?? String(e);
+  }
+}
+
+// 二进制输出不直接渲染，只显示大小和类型，并提供保存原始字节的按钮
+export const BinaryBlock: React.FC<{ summary: BinarySummary; dir: string }> = ({ summary, dir }) => {
+  const [status, setStatus] = useState<{ error: boolean; text: string } | null>(null);
+
+  const save = async () => {
+    const path = window.prompt('保存到', `${dir.replace(/\/$/, '')}/output.${summary.extension ?? 'bin'}`);
+    if (!path) return;
+    try {
+      const written = await invoke<number>('save_binary_output', { id: summary.id, path });
+      setStatus({ error: false, text: `已保存 ${formatSize(written)} 到 ${path}` });
+    } catch (e) {
+      setStatus({ error: true, text: describeSaveError(e) });
+    }
+  };
+
+  return (
+    <div className="text-gray-400 italic">
+      <span className="select-none">
+        [二进制输出 {formatSize(summary.size)}，{summary.mimeType}]
+        {summary.truncated && ' 仅保留了开头部分'}
+      </span>
+      <button
+        onClick={save}
+        className="ml-2 not-italic text-blue-400 hover:text-blue-300 underline select-none"
+      >
+        保存…
+      </button>
+      {status && (
+        <div className={status.error ? 'text-red-400 not-italic' : 'text-gray-500'}>{status.text}</div>
+      )}
+    </div>
+  );
+};
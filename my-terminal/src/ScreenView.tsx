@@ -0,0 +1,99 @@
+import React, { useEffect, useRef } from 'react';
+import { StyledText, Span } from './StyledText';
+
+// 后端终端模拟得到的屏幕快照（全屏程序使用备用屏幕时由后端推送）
+export interface ScreenSnapshot {
+  cols: number;
+  rows: number;
+  lines: Span[][];
+  cursorRow: number;
+  cursorCol: number;
+  modes: {
+    cursorVisible: boolean;
+    appCursorKeys: boolean;
+    bracketedPaste: boolean;
+  };
+  alternate: boolean;
+  title: string;
+}
+
+const FUNCTION_KEYS: { [key: string]: string } = {
+  F1: '\x1bOP', F2: '\x1bOQ', F3: '\x1bOR', F4: '\x1bOS',
+  F5: '\x1b[15~', F6: '\x1b[17~', F7: '\x1b[18~', F8: '\x1b[19~',
+  F9: '\x1b[20~', F10: '\x1b[21~', F11: '\x1b[23~', F12: '\x1b[24~',
+  Insert: '\x1b[2~', Delete: '\x1b[3~', PageUp: '\x1b[5~', PageDown: '\x1b[6~',
+};
+
+const CURSOR_KEYS: { [key: string]: string } = {
+  ArrowUp: 'A', ArrowDown: 'B', ArrowRight: 'C', ArrowLeft: 'D', Home: 'H', End: 'F',
+};
+
+// 将按键转换为终端输入序列；应用光标键模式下方向键使用 SS3 (ESC O) 形式
+function keyToSequence(e: React.KeyboardEvent, appCursorKeys: boolean): string | null {
+  if (e.key in CURSOR_KEYS) {
+    return (appCursorKeys ? '\x1bO' : '\x1b[') + CURSOR_KEYS[e.key];
+  }
+  if (e.key in FUNCTION_KEYS) return FUNCTION_KEYS[e.key];
+  switch (e.key) {
+    case 'Enter': return '\r';
+    case 'Backspace': return '\x7f';
+    case 'Tab': return e.shiftKey ? '\x1b[Z' : '\t';
+    case 'Escape': return '\x1b';
+  }
+  if (e.key.length !== 1 || e.metaKey) return null;
+  if (e.ctrlKey) {
+    const code = e.key.toUpperCase().charCodeAt(0);
+    if (code >= 0x40 && code <= 0x5f) return String.fromCharCode(code - 0x40);
+    if (e.key === ' ') return '\x00';
+    return null;
+  }
+  return e.altKey ? `\x1b${e.key}` : e.key;
+}
+
+export const ScreenView: React.FC<{
+  screen: ScreenSnapshot;
+  onInput: (data: string) => void;
+}> = ({ screen, onInput }) => {
+  const ref = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    ref.current?.focus();
+  }, []);
+
+  const handleKeyDown = (e: React.KeyboardEvent<HTMLDivElement>) => {
+    const data = keyToSequence(e, screen.modes.appCursorKeys);
+    if (data === null) return;
+    e.preventDefault();
+    onInput(data);
+  };
+
+  const handlePaste = (e: React.ClipboardEvent<HTMLDivElement>) => {
+    e.preventDefault();
+    const text = e.clipboardData.getData('text').replace(/\r?\n/g, '\r');
+    onInput(screen.modes.bracketedPaste ? `\x1b[200~${text}\x1b[201~` : text);
+  };
+
+  return (
+    <div
+      ref={ref}
+      tabIndex={0}
+      onKeyDown={handleKeyDown}
+      onPaste={handlePaste}
+      className="absolute inset-0 p-4 bg-[#1e2a3a] text-gray-300 outline-none z-40"
+    >
+      <div className="relative leading-5">
+        {screen.lines.map((line, i) => (
+          <div key={i} className="h-5 whitespace-pre">
+            <StyledText spans={line} />
+          </div>
+        ))}
+        {screen.modes.cursorVisible && (
+          <div
+            className="absolute h-5 bg-gray-300 opacity-60"
+            style={{ left: `${screen.cursorCol}ch`, top: `${screen.cursorRow * 1.25}rem`, width: '1ch' }}
+          />
+        )}
+      </div>
+    </div>
+  );
+};
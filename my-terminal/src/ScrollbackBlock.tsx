@@ -0,0 +1,97 @@
+import React, { useEffect, useRef, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { StyledText, Span } from './StyledText';
+
+// get_scrollback 的返回值：行号从会话开始计数，超出上限的旧行会被丢弃
+interface ScrollbackRange {
+  start: number;
+  lines: Span[][];
+  first: number;
+  end: number;
+}
+
+const LINE_HEIGHT = 20; // 与 leading-5 一致
+const VIRTUALIZE_ABOVE = 500; // 超过此行数时只渲染可见部分
+const OVERSCAN = 50;
+
+// 一条命令的输出块：内容保存在后端 scrollback 中，按需分段获取
+export const ScrollbackBlock: React.FC<{
+  sessionId: number;
+  first: number;
+  count: number;
+  scrollRoot: React.RefObject<HTMLDivElement | null>;
+}> = ({ sessionId, first, count, scrollRoot }) => {
+  const ref = useRef<HTMLDivElement>(null);
+  const virtualized = count > VIRTUALIZE_ABOVE;
+  const [visible, setVisible] = useState({ from: 0, to: Math.min(count, VIRTUALIZE_ABOVE) });
+  const [range, setRange] = useState<ScrollbackRange | null>(null);
+
+  // ✅ 根据滚动位置计算可见行范围
+  useEffect(() => {
+    const root = scrollRoot.current;
+    if (!virtualized || !root) {
+      setVisible({ from: 0, to: count });
+      return;
+    }
+    const update = () => {
+      const el = ref.current;
+      if (!el) return;
+      const top = el.getBoundingClientRect().top - root.getBoundingClientRect().top;
+      const from = Math.max(0, Math.floor(-top / LINE_HEIGHT) - OVERSCAN);
+      const to = Math.min(count, Math.ceil((root.clientHeight - top) / LINE_HEIGHT) + OVERSCAN);
+      setVisible((prev) => (prev.from === from && prev.to === to ? prev : { from, to: Math.max(from, to) }));
+    };
+    update();
+    root.addEventListener('scroll', update, { passive: true });
+    return () => root.removeEventListener('scroll', update);
+  }, [count, virtualized]);
+
+  useEffect(() => {
+    let cancelled = false;
+    invoke<ScrollbackRange>('get_scrollback', {
+      id: sessionId,
+      start: first + visible.from,
+      count: visible.to - visible.from,
+    })
+      .then((result) => {
+        if (!cancelled) setRange(result);
+      })
+      .catch(() => {});
+    return () => {
+      cancelled = true;
+    };
+  }, [sessionId, first, count, visible.from, visible.to]);
+
+  const dropped = range ? Math.min(count, Math.max(0, range.first - first)) : 0;
+  const notice = dropped > 0 && (
+    <div className="text-gray-500 italic select-none">… {dropped} 行已超出回滚上限</div>
+  );
+
+  if (!virtualized) {
+    return (
+      <div ref={ref} className="text-gray-300 whitespace-pre-wrap break-all">
+        {notice}
+        {range?.lines.map((line, i) => (
+          <div key={i} className="min-h-5">
+            <StyledText spans={line} />
+          </div>
+        ))}
+      </div>
+    );
+  }
+
+  return (
+    <div ref={ref} className="relative text-gray-300" style={{ height: count * LINE_HEIGHT }}>
+      {notice}
+      {range?.lines.map((line, i) => (
+        <div
+          key={range.start + i}
+          className="absolute left-0 right-0 h-5 whitespace-pre overflow-hidden"
+          style={{ top: (range.start - first + i) * LINE_HEIGHT }}
+        >
+          <StyledText spans={line} />
+        </div>
+      ))}
+    </div>
+  );
+};
@@ -0,0 +1,77 @@
+import React from 'react';
+
+// 后端解析 SGR 后发来的带样式文本片段；颜色为调色板序号或 "#rrggbb"
+export interface Span {
+  text: string;
+  fg?: number | string;
+  bg?: number | string;
+  bold?: boolean;
+  dim?: boolean;
+  italic?: boolean;
+  underline?: boolean;
+  inverse?: boolean;
+  hidden?: boolean;
+  strikethrough?: boolean;
+}
+
+// 16 色 ANSI 调色板（与深色背景搭配）
+const ANSI_COLORS = [
+  '#1e2a3a', '#f87171', '#4ade80', '#facc15', '#60a5fa', '#c084fc', '#22d3ee', '#d1d5db',
+  '#6b7280', '#fca5a5', '#86efac', '#fde047', '#93c5fd', '#d8b4fe', '#67e8f9', '#f9fafb',
+];
+
+const DEFAULT_FG = '#d1d5db';
+const DEFAULT_BG = '#1e2a3a';
+
+const hex = (n: number) => n.toString(16).padStart(2, '0');
+
+// 将调色板序号转换为 CSS 颜色：0-15 为主题色，16-231 为 6x6x6 色立方，232-255 为灰阶
+function paletteColor(index: number): string {
+  if (index < 16) return ANSI_COLORS[index];
+  if (index < 232) {
+    const levels = [0, 95, 135, 175, 215, 255];
+    const i = index - 16;
+    return `#${hex(levels[Math.floor(i / 36)])}${hex(levels[Math.floor(i / 6) % 6])}${hex(levels[i % 6])}`;
+  }
+  const gray = 8 + (index - 232) * 10;
+  return `#${hex(gray)}${hex(gray)}${hex(gray)}`;
+}
+
+function cssColor(color: number | string | undefined): string | undefined {
+  if (color === undefined) return undefined;
+  return typeof color === 'number' ? paletteColor(color) : color;
+}
+
+function spanStyle(span: Span): React.CSSProperties {
+  let fg = cssColor(span.fg);
+  let bg = cssColor(span.bg);
+  // 粗体的基础 8 色使用亮色版本，与常见终端一致
+  if (span.bold && typeof span.fg === 'number' && span.fg < 8) {
+    fg = paletteColor(span.fg + 8);
+  }
+  if (span.inverse) {
+    [fg, bg] = [bg ?? DEFAULT_BG, fg ?? DEFAULT_FG];
+  }
+  const decorations = [span.underline && 'underline', span.strikethrough && 'line-through'].filter(Boolean);
+  return {
+    color: fg,
+    backgroundColor: bg,
+    fontWeight: span.bold ? 'bold' : undefined,
+    fontStyle: span.italic ? 'italic' : undefined,
+    opacity: span.dim ? 0.6 : undefined,
+    visibility: span.hidden ? 'hidden' : undefined,
+    textDecoration: decorations.length ? decorations.join(' ') : undefined,
+  };
+}
+
+export const StyledText: React.FC<{ spans: Span[] }> = ({ spans }) => (
+  <>
+    {spans.map((span, i) =>
+      Object.keys(span).length === 1 ? (
+        <React.Fragment key={i}>{span.text}</React.Fragment>
+      ) : (
+        <span key={i} style={spanStyle(span)}>{span.text}</span>
+      )
+    )}
+  </>
+);